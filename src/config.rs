@@ -0,0 +1,727 @@
+//! Configuration file
+//!
+//! A small subset of TOML is understood, enough for our needs :
+//! comments, `[section]` headers, and `key = value` lines where value is a
+//! string, an integer, a boolean or an array of strings.
+//!
+//! A `gemini2html.toml` at the root of a capsule is loaded automatically,
+//! see [`Config::for_capsule`].
+//!
+//! ```toml
+//! # gemini2html.toml
+//! extension = "gmi"
+//! log_level = "warn"
+//!
+//! [site]
+//! name = "My capsule"
+//! base_url = "https://example.org/"
+//! language = "en"
+//! ```
+
+use crate::feed::{FeedContent, FeedOptions};
+use crate::gemlog::GemlogOptions;
+use crate::site::SiteOptions;
+use crate::template::Template;
+use crate::{Gemini2HtmlError, Operation, Options, Result, Stylesheet, Theme};
+use log::warn;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the configuration file at the root of a capsule
+pub const CONFIG_FILE_NAME: &str = "gemini2html.toml";
+
+/// A value of the configuration file
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<String>),
+}
+
+/// Settings read from a configuration file, every setting is optional
+/// command line flags take precedence over them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    /// Name of the site, `name` in the `[site]` section
+    pub site_name: Option<String>,
+    /// Url where the site is published, `base_url` in the `[site]` section
+    pub base_url: Option<String>,
+    /// Language of the pages, `language` in the `[site]` section
+    pub language: Option<String>,
+    /// Author of the pages, `author` in the `[site]` section
+    pub author: Option<String>,
+    /// Extension of gemini files, without the dot
+    pub extension: Option<String>,
+    /// Extensions of files displayed as images
+    pub image_extensions: Option<Vec<String>>,
+    /// Globs of the files to build, see [`crate::site::SiteOptions::include`]
+    pub include: Option<Vec<String>>,
+    /// Globs of the files not to build, see [`crate::site::SiteOptions::exclude`]
+    pub exclude: Option<Vec<String>>,
+    /// Build drafts too
+    pub drafts: Option<bool>,
+    /// Html inserted in the footer of each page
+    pub credits: Option<String>,
+    /// Built-in stylesheet
+    pub theme: Option<Theme>,
+    /// Css file replacing the built-in stylesheet
+    pub css: Option<PathBuf>,
+    /// Css file added after the built-in stylesheet
+    pub extra_css: Option<PathBuf>,
+    /// `inline`, `shared` for a single `style.css`, or the url of an external stylesheet
+    pub stylesheet: Option<Stylesheet>,
+    /// Gemlog page of the feeds, relative to the root of the capsule,
+    /// `source` in the `[feed]` section
+    pub feed_source: Option<PathBuf>,
+    /// Write an Atom feed, `atom` in the `[feed]` section
+    pub atom: Option<bool>,
+    /// Write a RSS feed, `rss` in the `[feed]` section
+    pub rss: Option<bool>,
+    /// Maximum number of entries of the feeds, `limit` in the `[feed]` section
+    pub feed_limit: Option<usize>,
+    /// `full` or `summary`, `content` in the `[feed]` section
+    pub feed_content: Option<FeedContent>,
+    /// Directory of the posts whose index is generated, `posts` in the `[gemlog]` section
+    pub gemlog_posts: Option<PathBuf>,
+    /// Title of the generated index, `title` in the `[gemlog]` section
+    pub gemlog_title: Option<String>,
+    /// Html template of the pages, see [`crate::template`]
+    pub template: Option<PathBuf>,
+    /// Log level : `error`, `warn`, `info`, `debug` or `trace`
+    pub log_level: Option<String>,
+    /// Port used by `serve`
+    pub port: Option<u16>,
+    /// Number of threads of a build, one per cpu by default
+    pub jobs: Option<usize>,
+    /// Build every file, ignoring the previous build
+    pub force: Option<bool>,
+    /// Remove the outputs of previous builds whose source was removed
+    pub clean: Option<bool>,
+    /// List these outputs without removing them, it wins over `clean`
+    pub dry_run: Option<bool>,
+}
+
+impl Config {
+    /// Read a configuration file, relative paths inside are relative to the file
+    pub fn load(path: &Path) -> Result<Config> {
+        let content =
+            fs::read_to_string(path).map_err(Gemini2HtmlError::io(Operation::Read, path))?;
+        let mut config =
+            Config::parse(&content).map_err(|(line, message)| Gemini2HtmlError::Config {
+                path: path.to_path_buf(),
+                line,
+                message,
+            })?;
+        if let Some(directory) = path.parent() {
+            config.css = config.css.map(|css| directory.join(css));
+            config.extra_css = config.extra_css.map(|css| directory.join(css));
+            config.template = config.template.map(|template| directory.join(template));
+        }
+        Ok(config)
+    }
+
+    /// Read the configuration file at the root of a capsule, if there is one
+    pub fn for_capsule(directory: &Path) -> Result<Config> {
+        let path = directory.join(CONFIG_FILE_NAME);
+        match path.is_file() {
+            true => Config::load(&path),
+            false => Ok(Config::default()),
+        }
+    }
+
+    /// Parse a configuration, errors are returned with their line number
+    pub fn parse(content: &str) -> std::result::Result<Config, (usize, String)> {
+        let values = parse_values(content)?;
+        let mut config = Config::default();
+        for (key, (line, value)) in values {
+            let invalid = |expected: &str| (line, format!("`{key}` must be {expected}"));
+            match key.as_str() {
+                "site.name" => config.site_name = Some(string(value).ok_or(invalid("a string"))?),
+                "site.base_url" => {
+                    config.base_url = Some(string(value).ok_or(invalid("a string"))?)
+                }
+                "site.language" => {
+                    config.language = Some(string(value).ok_or(invalid("a string"))?)
+                }
+                "site.author" => config.author = Some(string(value).ok_or(invalid("a string"))?),
+                "image_extensions" => {
+                    config.image_extensions =
+                        Some(array(value).ok_or(invalid("an array of strings"))?)
+                }
+                "include" => {
+                    config.include = Some(array(value).ok_or(invalid("an array of strings"))?)
+                }
+                "exclude" => {
+                    config.exclude = Some(array(value).ok_or(invalid("an array of strings"))?)
+                }
+                "drafts" => config.drafts = Some(boolean(value).ok_or(invalid("a boolean"))?),
+                "extension" => config.extension = Some(string(value).ok_or(invalid("a string"))?),
+                "credits" => config.credits = Some(string(value).ok_or(invalid("a string"))?),
+                "css" => config.css = Some(string(value).ok_or(invalid("a string"))?.into()),
+                "theme" => {
+                    config.theme = Some(
+                        string(value)
+                            .and_then(|name| Theme::from_name(&name))
+                            .ok_or(invalid(&format!("one of {}", theme_names())))?,
+                    )
+                }
+                "extra_css" => {
+                    config.extra_css = Some(string(value).ok_or(invalid("a string"))?.into())
+                }
+                "stylesheet" => {
+                    config.stylesheet = Some(
+                        string(value)
+                            .and_then(|name| Stylesheet::from_name(&name))
+                            .ok_or(invalid(
+                                "`inline`, `shared`, or an url with a scheme or an absolute path",
+                            ))?,
+                    )
+                }
+                "feed.source" => {
+                    config.feed_source = Some(string(value).ok_or(invalid("a string"))?.into())
+                }
+                "feed.atom" => config.atom = Some(boolean(value).ok_or(invalid("a boolean"))?),
+                "feed.rss" => config.rss = Some(boolean(value).ok_or(invalid("a boolean"))?),
+                "feed.limit" => {
+                    config.feed_limit = Some(
+                        integer(value)
+                            .and_then(|limit| usize::try_from(limit).ok())
+                            .ok_or(invalid("a positive number"))?,
+                    )
+                }
+                "feed.content" => {
+                    config.feed_content = Some(
+                        match string(value)
+                            .ok_or(invalid("`full` or `summary`"))?
+                            .as_str()
+                        {
+                            "full" => FeedContent::Full,
+                            "summary" => FeedContent::Summary,
+                            _ => return Err(invalid("`full` or `summary`")),
+                        },
+                    )
+                }
+                "gemlog.posts" => {
+                    config.gemlog_posts = Some(string(value).ok_or(invalid("a string"))?.into())
+                }
+                "gemlog.title" => {
+                    config.gemlog_title = Some(string(value).ok_or(invalid("a string"))?)
+                }
+                "template" => {
+                    config.template = Some(string(value).ok_or(invalid("a string"))?.into())
+                }
+                "log_level" => config.log_level = Some(string(value).ok_or(invalid("a string"))?),
+                "jobs" => {
+                    config.jobs = Some(
+                        integer(value)
+                            .and_then(|jobs| usize::try_from(jobs).ok())
+                            .filter(|jobs| *jobs > 0)
+                            .ok_or(invalid("a number of threads"))?,
+                    )
+                }
+                "port" => {
+                    config.port = Some(
+                        integer(value)
+                            .and_then(|port| u16::try_from(port).ok())
+                            .ok_or(invalid("a port number"))?,
+                    )
+                }
+                "force" => config.force = Some(boolean(value).ok_or(invalid("a boolean"))?),
+                "clean" => config.clean = Some(boolean(value).ok_or(invalid("a boolean"))?),
+                "dry_run" => config.dry_run = Some(boolean(value).ok_or(invalid("a boolean"))?),
+                _ => warn!("unknown configuration key `{key}` at line {line}, ignored"),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Fill settings missing here with the ones of `fallback`
+    pub fn or(self, fallback: Config) -> Config {
+        Config {
+            site_name: self.site_name.or(fallback.site_name),
+            base_url: self.base_url.or(fallback.base_url),
+            language: self.language.or(fallback.language),
+            author: self.author.or(fallback.author),
+            extension: self.extension.or(fallback.extension),
+            image_extensions: self.image_extensions.or(fallback.image_extensions),
+            include: self.include.or(fallback.include),
+            exclude: self.exclude.or(fallback.exclude),
+            drafts: self.drafts.or(fallback.drafts),
+            credits: self.credits.or(fallback.credits),
+            theme: self.theme.or(fallback.theme),
+            css: self.css.or(fallback.css),
+            extra_css: self.extra_css.or(fallback.extra_css),
+            stylesheet: self.stylesheet.or(fallback.stylesheet),
+            feed_source: self.feed_source.or(fallback.feed_source),
+            atom: self.atom.or(fallback.atom),
+            rss: self.rss.or(fallback.rss),
+            feed_limit: self.feed_limit.or(fallback.feed_limit),
+            feed_content: self.feed_content.or(fallback.feed_content),
+            gemlog_posts: self.gemlog_posts.or(fallback.gemlog_posts),
+            gemlog_title: self.gemlog_title.or(fallback.gemlog_title),
+            template: self.template.or(fallback.template),
+            log_level: self.log_level.or(fallback.log_level),
+            port: self.port.or(fallback.port),
+            jobs: self.jobs.or(fallback.jobs),
+            force: self.force.or(fallback.force),
+            clean: self.clean.or(fallback.clean),
+            dry_run: self.dry_run.or(fallback.dry_run),
+        }
+    }
+
+    /// Conversion options from this configuration, the css and template files are read here
+    pub fn options(&self) -> Result<Options> {
+        let mut options = Options {
+            site_name: self.site_name.clone(),
+            base_url: self.base_url.clone(),
+            language: self.language.clone(),
+            author: self.author.clone(),
+            feed_links: self.feed().map(|feed| feed.links()).unwrap_or_default(),
+            ..Options::default()
+        };
+        if let Some(extension) = &self.extension {
+            options.gemini_extension = extension.clone();
+        }
+        if let Some(image_extensions) = &self.image_extensions {
+            options.image_extensions = image_extensions.clone();
+        }
+        if let Some(credits) = &self.credits {
+            options.credits = credits.clone();
+        }
+        if let Some(theme) = self.theme {
+            options.theme = theme;
+        }
+        if let Some(css) = &self.css {
+            options.css =
+                Some(fs::read_to_string(css).map_err(Gemini2HtmlError::io(Operation::Read, css))?);
+        }
+        if let Some(css) = &self.extra_css {
+            options.extra_css =
+                Some(fs::read_to_string(css).map_err(Gemini2HtmlError::io(Operation::Read, css))?);
+        }
+        if let Some(stylesheet) = &self.stylesheet {
+            options.stylesheet = stylesheet.clone();
+        }
+        if let Some(template) = &self.template {
+            options.template = Some(Template::load(template)?);
+        }
+        Ok(options)
+    }
+
+    /// Site build options from this configuration
+    pub fn site_options(&self) -> SiteOptions {
+        SiteOptions {
+            feed: self.feed(),
+            gemlog: self.gemlog(),
+            include: self.include.clone().unwrap_or_default(),
+            exclude: self.exclude.clone().unwrap_or_default(),
+            drafts: self.drafts.unwrap_or_default(),
+        }
+    }
+
+    fn gemlog(&self) -> Option<GemlogOptions> {
+        let posts = self.gemlog_posts.as_ref()?;
+        let mut gemlog = GemlogOptions {
+            posts: posts.clone(),
+            ..GemlogOptions::default()
+        };
+        if let Some(title) = &self.gemlog_title {
+            gemlog.title = title.clone();
+        }
+        Some(gemlog)
+    }
+
+    fn feed(&self) -> Option<FeedOptions> {
+        let (atom, rss) = (self.atom == Some(true), self.rss == Some(true));
+        if !atom && !rss {
+            return None;
+        }
+        let mut feed = FeedOptions {
+            atom,
+            rss,
+            limit: self.feed_limit,
+            content: self.feed_content.unwrap_or_default(),
+            ..FeedOptions::default()
+        };
+        // the feeds of a gemlog come from its generated index by default
+        if let Some(source) = &self.feed_source {
+            feed.source = source.clone();
+        } else if let Some(gemlog) = self.gemlog() {
+            feed.source = gemlog.index();
+        }
+        Some(feed)
+    }
+}
+
+/// Names of the built-in themes, for error messages
+fn theme_names() -> String {
+    Theme::ALL.map(Theme::name).join(", ")
+}
+
+fn string(value: Value) -> Option<String> {
+    match value {
+        Value::String(string) => Some(string),
+        _ => None,
+    }
+}
+
+fn array(value: Value) -> Option<Vec<String>> {
+    match value {
+        Value::Array(array) => Some(array),
+        _ => None,
+    }
+}
+
+fn boolean(value: Value) -> Option<bool> {
+    match value {
+        Value::Boolean(boolean) => Some(boolean),
+        _ => None,
+    }
+}
+
+fn integer(value: Value) -> Option<i64> {
+    match value {
+        Value::Integer(integer) => Some(integer),
+        _ => None,
+    }
+}
+
+/// Read all `key = value` lines, keys inside a section are prefixed : `section.key`
+/// values are returned with their line number
+fn parse_values(
+    content: &str,
+) -> std::result::Result<BTreeMap<String, (usize, Value)>, (usize, String)> {
+    let mut values = BTreeMap::new();
+    let mut section = String::new();
+    for (index, line) in content.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            let header = strip_comment(header);
+            section = header
+                .strip_suffix(']')
+                .map(|name| name.trim().to_string())
+                .filter(|name| is_key(name))
+                .ok_or((line_number, format!("invalid section header `{line}`")))?;
+            continue;
+        }
+        let (key, value) = line.split_once('=').ok_or((
+            line_number,
+            format!("expected `key = value`, found `{line}`"),
+        ))?;
+        let key = key.trim();
+        if !is_key(key) {
+            return Err((line_number, format!("invalid key `{key}`")));
+        }
+        let value = parse_value(value.trim()).map_err(|message| (line_number, message))?;
+        let key = match section.is_empty() {
+            true => key.to_string(),
+            false => format!("{section}.{key}"),
+        };
+        if values.insert(key.clone(), (line_number, value)).is_some() {
+            return Err((line_number, format!("duplicate key `{key}`")));
+        }
+    }
+    Ok(values)
+}
+
+/// Bare keys only : letters, digits, `_` and `-`
+fn is_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Remove a trailing comment, outside of strings
+fn strip_comment(text: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (position, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return text[..position].trim_end(),
+            _ => (),
+        }
+    }
+    text.trim_end()
+}
+
+/// Parse a value, followed by an optional comment
+fn parse_value(value: &str) -> std::result::Result<Value, String> {
+    let value = strip_comment(value);
+    if value.starts_with('"') {
+        let (string, rest) = parse_string(value)?;
+        match rest.is_empty() {
+            true => Ok(Value::String(string)),
+            false => Err(format!("unexpected `{rest}` after string")),
+        }
+    } else if let Some(array) = value.strip_prefix('[') {
+        let mut rest = array.trim_start();
+        let mut strings = Vec::new();
+        loop {
+            if let Some(after) = rest.strip_prefix(']') {
+                return match after.trim().is_empty() {
+                    true => Ok(Value::Array(strings)),
+                    false => Err(format!("unexpected `{after}` after array")),
+                };
+            }
+            let (string, after) = parse_string(rest)?;
+            strings.push(string);
+            rest = after.trim_start();
+            rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+            if rest.is_empty() {
+                return Err("unclosed array, arrays must fit on one line".to_string());
+            }
+        }
+    } else if value == "true" || value == "false" {
+        Ok(Value::Boolean(value == "true"))
+    } else if let Ok(integer) = value.replace('_', "").parse() {
+        Ok(Value::Integer(integer))
+    } else {
+        Err(format!("invalid value `{value}`"))
+    }
+}
+
+/// Parse a basic string, return it with the rest of the text
+fn parse_string(text: &str) -> std::result::Result<(String, &str), String> {
+    let Some(content) = text.strip_prefix('"') else {
+        return Err(format!("expected a string, found `{text}`"));
+    };
+    let mut string = String::new();
+    let mut chars = content.char_indices();
+    while let Some((position, c)) = chars.next() {
+        match c {
+            '"' => return Ok((string, content[position + 1..].trim_start())),
+            '\\' => match chars.next().map(|(_, c)| c) {
+                Some('n') => string.push('\n'),
+                Some('t') => string.push('\t'),
+                Some('"') => string.push('"'),
+                Some('\\') => string.push('\\'),
+                other => return Err(format!("invalid escape `\\{}`", other.unwrap_or(' '))),
+            },
+            c => string.push(c),
+        }
+    }
+    Err("unclosed string".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feed::FeedLinks;
+    use crate::test_tree::TestTree;
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::parse(
+            "# a comment\nextension = \"gemini\" # another comment\ncredits = \"<b>me</b> \\\"#1\\\"\"\ncss = \"my.css\"\nlog_level = \"warn\"\nport = 1_965\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                extension: Some("gemini".to_string()),
+                credits: Some("<b>me</b> \"#1\"".to_string()),
+                css: Some(PathBuf::from("my.css")),
+                log_level: Some("warn".to_string()),
+                port: Some(1965),
+                ..Config::default()
+            }
+        );
+    }
+    #[test]
+    fn test_parse_site_config() {
+        let config = Config::parse(
+            "image_extensions = [\"png\", \"jxl\"]\nexclude = [\"*.log\"]\ndrafts = true\n[site]\nname = \"My capsule\"\nbase_url = \"https://example.org/\"\nlanguage = \"en\"\nauthor = \"me\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.site_name, Some("My capsule".to_string()));
+        assert_eq!(config.base_url, Some("https://example.org/".to_string()));
+        let options = config.options().unwrap();
+        assert_eq!(options.language, Some("en".to_string()));
+        assert_eq!(options.author, Some("me".to_string()));
+        assert_eq!(options.image_extensions, ["png", "jxl"]);
+        assert_eq!(options.stylesheet, Stylesheet::Inline);
+        assert_eq!(options.theme, Theme::Default);
+        assert_eq!(options.feed_links, FeedLinks::default());
+        let site_options = config.site_options();
+        assert_eq!(site_options.exclude, ["*.log"]);
+        assert!(site_options.include.is_empty());
+        assert!(site_options.drafts);
+        assert_eq!(site_options.feed, None);
+        let config = Config::parse("[feed]\nsource = \"gemlog/index.gmi\"\natom = true\n").unwrap();
+        assert_eq!(
+            config.options().unwrap().feed_links,
+            FeedLinks {
+                atom: true,
+                rss: false
+            }
+        );
+        assert_eq!(
+            config.site_options().feed,
+            Some(FeedOptions {
+                source: PathBuf::from("gemlog/index.gmi"),
+                atom: true,
+                ..FeedOptions::default()
+            })
+        );
+        let config =
+            Config::parse("[feed]\nrss = true\nlimit = 10\ncontent = \"summary\"\n").unwrap();
+        assert_eq!(
+            config.site_options().feed,
+            Some(FeedOptions {
+                atom: false,
+                rss: true,
+                limit: Some(10),
+                content: FeedContent::Summary,
+                ..FeedOptions::default()
+            })
+        );
+        let config =
+            Config::parse("[gemlog]\nposts = \"posts\"\ntitle = \"News\"\n[feed]\natom = true\n")
+                .unwrap();
+        let site_options = config.site_options();
+        assert_eq!(
+            site_options.gemlog,
+            Some(GemlogOptions {
+                posts: PathBuf::from("posts"),
+                title: "News".to_string(),
+            })
+        );
+        assert_eq!(
+            site_options.feed.unwrap().source,
+            PathBuf::from("posts/index.gmi")
+        );
+        assert_eq!(
+            Config::parse("[feed]\ncontent = \"all\"").unwrap_err(),
+            (2, "`feed.content` must be `full` or `summary`".to_string())
+        );
+        assert_eq!(
+            Config::parse("theme = \"high-contrast\"").unwrap().theme,
+            Some(Theme::HighContrast)
+        );
+        assert_eq!(
+            Config::parse("theme = \"pink\"").unwrap_err(),
+            (
+                1,
+                "`theme` must be one of default, terminal, high-contrast, print".to_string()
+            )
+        );
+        let config = Config::parse("stylesheet = \"/css/main.css\"").unwrap();
+        assert_eq!(
+            config.options().unwrap().stylesheet,
+            Stylesheet::External("/css/main.css".to_string())
+        );
+        assert_eq!(
+            Config::parse("stylesheet = \"shraed\"").unwrap_err(),
+            (
+                1,
+                "`stylesheet` must be `inline`, `shared`, or an url with a scheme or an absolute path"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            Config::parse("image_extensions = \"png\"").unwrap_err(),
+            (
+                1,
+                "`image_extensions` must be an array of strings".to_string()
+            )
+        );
+    }
+    #[test]
+    fn test_config_for_capsule() {
+        let directory = TestTree::new("config_for_capsule");
+        // no configuration file : defaults
+        assert_eq!(Config::for_capsule(&directory).unwrap(), Config::default());
+        fs::write(
+            directory.join(CONFIG_FILE_NAME),
+            "css = \"style.css\"\ntemplate = \"layout.html\"\n[site]\nname = \"x\"\n",
+        )
+        .unwrap();
+        let config = Config::for_capsule(&directory).unwrap();
+        assert_eq!(config.site_name, Some("x".to_string()));
+        assert_eq!(config.css, Some(directory.join("style.css")));
+        // the template is read with the options
+        fs::write(directory.join("style.css"), "").unwrap();
+        fs::write(directory.join("layout.html"), "{{ nothing }}").unwrap();
+        assert!(matches!(
+            config.options(),
+            Err(Gemini2HtmlError::Template { line: 1, .. })
+        ));
+        fs::write(directory.join("layout.html"), "{{ content }}").unwrap();
+        assert!(config.options().unwrap().template.is_some());
+    }
+    #[test]
+    fn test_parse_config_errors() {
+        assert_eq!(
+            Config::parse("extension = 42").unwrap_err(),
+            (1, "`extension` must be a string".to_string())
+        );
+        assert_eq!(
+            Config::parse("\nport = 100000").unwrap_err(),
+            (2, "`port` must be a port number".to_string())
+        );
+        assert_eq!(
+            Config::parse("jobs = 0").unwrap_err(),
+            (1, "`jobs` must be a number of threads".to_string())
+        );
+        assert_eq!(
+            Config::parse("credits = \"unclosed").unwrap_err(),
+            (1, "unclosed string".to_string())
+        );
+        assert_eq!(
+            Config::parse("extension").unwrap_err(),
+            (1, "expected `key = value`, found `extension`".to_string())
+        );
+        assert!(Config::parse("a = 1\na = 2").is_err());
+        assert!(Config::parse("a = \"b\" c").is_err());
+    }
+    #[test]
+    fn test_parse_values() {
+        let values =
+            parse_values("top = true\n[site]\nname = \"x\"\nlist = [\"a\", \"b\",]\n").unwrap();
+        assert_eq!(values["top"].1, Value::Boolean(true));
+        assert_eq!(values["site.name"].1, Value::String("x".to_string()));
+        assert_eq!(
+            values["site.list"].1,
+            Value::Array(vec!["a".to_string(), "b".to_string()])
+        );
+    }
+    #[test]
+    fn test_config_or() {
+        let flags = Config {
+            extension: Some("gemini".to_string()),
+            ..Config::default()
+        };
+        let file = Config {
+            extension: Some("gmi".to_string()),
+            port: Some(1965),
+            ..Config::default()
+        };
+        let config = flags.or(file);
+        assert_eq!(config.extension, Some("gemini".to_string()));
+        assert_eq!(config.port, Some(1965));
+        // boolean flags are merged too
+        let flags = Config {
+            force: Some(true),
+            ..Config::default()
+        };
+        let file = Config::parse("force = false\nclean = true\n").unwrap();
+        let config = flags.or(file);
+        assert_eq!(config.force, Some(true));
+        assert_eq!(config.clean, Some(true));
+        assert_eq!(config.dry_run, None);
+    }
+    #[test]
+    fn test_unknown_keys_are_ignored() {
+        assert_eq!(Config::parse("something = 1").unwrap(), Config::default());
+    }
+}
@@ -0,0 +1,255 @@
+//! Error handling
+//!
+//! Every failure carries the path involved, unless it happened on a stream,
+//! the operation which failed, and the underlying error as `source()`.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf, StripPrefixError};
+
+pub type Result<T> = std::result::Result<T, Gemini2HtmlError>;
+
+/// Operation which failed
+/// new operations may be added, matches need a wildcard arm
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Operation {
+    Read,
+    Write,
+    ReadDir,
+    CreateDir,
+    Copy,
+    Remove,
+    StripPrefix,
+    Listen,
+    Watch,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operation = match self {
+            Operation::Read => "read",
+            Operation::Write => "write",
+            Operation::ReadDir => "read directory",
+            Operation::CreateDir => "create directory",
+            Operation::Copy => "copy",
+            Operation::Remove => "remove",
+            Operation::StripPrefix => "strip prefix of",
+            Operation::Listen => "listen on",
+            Operation::Watch => "watch",
+        };
+        write!(f, "{operation}")
+    }
+}
+
+/// Failure of a conversion or of a site build
+/// new kinds of failure may be added, matches need a wildcard arm
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Gemini2HtmlError {
+    /// A filesystem operation failed, `path` is `-` for standard input and output
+    /// and the address for network operations
+    Io {
+        operation: Operation,
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// A read or a write failed on a stream without path,
+    /// like the reader and the writer of [`crate::convert`]
+    Stream {
+        operation: Operation,
+        source: io::Error,
+    },
+    /// A path is not inside the expected directory
+    StripPrefix {
+        path: PathBuf,
+        prefix: PathBuf,
+        source: StripPrefixError,
+    },
+    /// The target directory of a build contains the source directory
+    NestedDirectories {
+        source_directory: PathBuf,
+        target_directory: PathBuf,
+    },
+    /// Invalid configuration file
+    Config {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    /// Invalid template file
+    Template {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    /// A feed can't be built from a gemlog page
+    Feed { path: PathBuf, message: String },
+    /// The gemlog index can't be generated
+    Gemlog { path: PathBuf, message: String },
+}
+
+impl Gemini2HtmlError {
+    /// Build an error from an `io::Error`, to be used with `map_err`
+    pub fn io(operation: Operation, path: &Path) -> impl FnOnce(io::Error) -> Self {
+        move |source| Gemini2HtmlError::Io {
+            operation,
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    /// Operation which failed, if any
+    pub fn operation(&self) -> Option<Operation> {
+        match self {
+            Gemini2HtmlError::Io { operation, .. } | Gemini2HtmlError::Stream { operation, .. } => {
+                Some(*operation)
+            }
+            Gemini2HtmlError::StripPrefix { .. } => Some(Operation::StripPrefix),
+            Gemini2HtmlError::NestedDirectories { .. }
+            | Gemini2HtmlError::Config { .. }
+            | Gemini2HtmlError::Template { .. }
+            | Gemini2HtmlError::Feed { .. }
+            | Gemini2HtmlError::Gemlog { .. } => None,
+        }
+    }
+
+    /// Path involved in the failure, if any
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Gemini2HtmlError::Io { path, .. }
+            | Gemini2HtmlError::StripPrefix { path, .. }
+            | Gemini2HtmlError::Config { path, .. }
+            | Gemini2HtmlError::Template { path, .. }
+            | Gemini2HtmlError::Feed { path, .. }
+            | Gemini2HtmlError::Gemlog { path, .. } => Some(path),
+            Gemini2HtmlError::NestedDirectories {
+                target_directory, ..
+            } => Some(target_directory),
+            Gemini2HtmlError::Stream { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for Gemini2HtmlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Gemini2HtmlError::Io {
+                operation,
+                path,
+                source,
+            } => write!(f, "unable to {operation} {} : {source}", path.display()),
+            Gemini2HtmlError::Stream { operation, source } => {
+                write!(f, "unable to {operation} stream : {source}")
+            }
+            Gemini2HtmlError::StripPrefix { path, prefix, .. } => write!(
+                f,
+                "unable to strip prefix of {} : not inside {}",
+                path.display(),
+                prefix.display()
+            ),
+            Gemini2HtmlError::NestedDirectories {
+                source_directory,
+                target_directory,
+            } => write!(
+                f,
+                "unable to build {} into {} : the target directory must not contain the source directory",
+                source_directory.display(),
+                target_directory.display()
+            ),
+            Gemini2HtmlError::Config {
+                path,
+                line,
+                message,
+            } => write!(
+                f,
+                "invalid configuration {}:{line} : {message}",
+                path.display()
+            ),
+            Gemini2HtmlError::Template {
+                path,
+                line,
+                message,
+            } => write!(f, "invalid template {}:{line} : {message}", path.display()),
+            Gemini2HtmlError::Feed { path, message } => {
+                write!(
+                    f,
+                    "unable to build feed from {} : {message}",
+                    path.display()
+                )
+            }
+            Gemini2HtmlError::Gemlog { path, message } => {
+                write!(
+                    f,
+                    "unable to generate gemlog index {} : {message}",
+                    path.display()
+                )
+            }
+        }
+    }
+}
+
+impl Error for Gemini2HtmlError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Gemini2HtmlError::Io { source, .. } | Gemini2HtmlError::Stream { source, .. } => {
+                Some(source)
+            }
+            Gemini2HtmlError::StripPrefix { source, .. } => Some(source),
+            Gemini2HtmlError::NestedDirectories { .. }
+            | Gemini2HtmlError::Config { .. }
+            | Gemini2HtmlError::Template { .. }
+            | Gemini2HtmlError::Feed { .. }
+            | Gemini2HtmlError::Gemlog { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_error() {
+        let path = Path::new("some/file.gmi");
+        let error = Gemini2HtmlError::io(Operation::Read, path)(io::Error::new(
+            io::ErrorKind::NotFound,
+            "not found",
+        ));
+        assert_eq!(error.operation(), Some(Operation::Read));
+        assert_eq!(error.path(), Some(path));
+        assert_eq!(
+            error.to_string(),
+            "unable to read some/file.gmi : not found"
+        );
+        assert_eq!(error.source().unwrap().to_string(), "not found");
+    }
+    #[test]
+    fn test_stream_error() {
+        let error = Gemini2HtmlError::Stream {
+            operation: Operation::Write,
+            source: io::Error::new(io::ErrorKind::BrokenPipe, "broken pipe"),
+        };
+        assert_eq!(error.operation(), Some(Operation::Write));
+        assert_eq!(error.path(), None);
+        assert_eq!(error.to_string(), "unable to write stream : broken pipe");
+        assert!(error.source().is_some());
+    }
+    #[test]
+    fn test_strip_prefix_error() {
+        let path = Path::new("a/b");
+        let prefix = Path::new("c");
+        let error = Gemini2HtmlError::StripPrefix {
+            path: path.to_path_buf(),
+            prefix: prefix.to_path_buf(),
+            source: path.strip_prefix(prefix).unwrap_err(),
+        };
+        assert_eq!(error.operation(), Some(Operation::StripPrefix));
+        assert_eq!(
+            error.to_string(),
+            "unable to strip prefix of a/b : not inside c"
+        );
+        assert!(error.source().is_some());
+    }
+}
@@ -0,0 +1,750 @@
+//! Gemlog feeds
+//!
+//! Entries come from the dated links of a gemlog page, as described in the
+//! "Subscribing to Gemini pages" companion specification :
+//!
+//! ```text
+//! # My gemlog
+//! => 2026-10-01-post.gmi 2026-10-01 Title of the post
+//! ```
+//!
+//! Feeds need absolute urls, they are built from the `base_url` of the site.
+
+use crate::ignore::SourceFilter;
+use crate::site::{local_link_path, percent_encode, resolve_local_link};
+use crate::{
+    Gemini2HtmlError, Options, ParsedGemini, Result, escaped_attribute, html_content, parse_gemini,
+    read_from_file, split_link, write_to_file,
+};
+use log::{info, warn};
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+
+/// Name of the Atom feed, written at the root of the site
+pub const ATOM_FILE_NAME: &str = "atom.xml";
+/// Name of the RSS feed, written at the root of the site
+pub const RSS_FILE_NAME: &str = "rss.xml";
+
+/// What entries of a feed contain
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FeedContent {
+    /// The whole post, converted to html
+    #[default]
+    Full,
+    /// The first paragraph of the post, as text
+    Summary,
+}
+
+/// Feeds to generate
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedOptions {
+    /// Gemlog page, relative to the root of the capsule
+    pub source: PathBuf,
+    /// Write an Atom feed
+    pub atom: bool,
+    /// Write a RSS 2.0 feed
+    pub rss: bool,
+    /// Maximum number of entries, the newest ones are kept
+    pub limit: Option<usize>,
+    pub content: FeedContent,
+}
+
+impl Default for FeedOptions {
+    fn default() -> Self {
+        FeedOptions {
+            source: PathBuf::from("index.gmi"),
+            atom: true,
+            rss: false,
+            limit: None,
+            content: FeedContent::Full,
+        }
+    }
+}
+
+impl FeedOptions {
+    /// Feeds to link from the pages
+    pub fn links(&self) -> FeedLinks {
+        FeedLinks {
+            atom: self.atom,
+            rss: self.rss,
+        }
+    }
+}
+
+/// Feeds of the site, linked from the head of each page
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FeedLinks {
+    pub atom: bool,
+    pub rss: bool,
+}
+
+/// A dated link of a gemlog page
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatedLink {
+    pub url: String,
+    /// `YYYY-MM-DD`
+    pub date: String,
+    pub title: String,
+}
+
+/// Dated links of a page, in the order of the page
+pub(crate) fn dated_links(parsed_gemini: &[ParsedGemini]) -> Vec<DatedLink> {
+    parsed_gemini
+        .iter()
+        .filter_map(|line| match line {
+            ParsedGemini::Link(link) => dated_link(link),
+            _ => None,
+        })
+        .collect()
+}
+
+/// A link whose description starts with a date, the rest is the title
+fn dated_link(link: &str) -> Option<DatedLink> {
+    let (url, description) = split_link(link);
+    let description = description?;
+    let date = description.get(..10).filter(|date| is_date(date))?;
+    let title = description[10..]
+        .trim_start_matches([' ', '\t', '-', ':', '—'])
+        .trim_end();
+    Some(DatedLink {
+        url: url.to_string(),
+        date: date.to_string(),
+        title: match title.is_empty() {
+            true => date.to_string(),
+            false => title.to_string(),
+        },
+    })
+}
+
+/// A `YYYY-MM-DD` date, which exists in the calendar
+pub(crate) fn is_date(date: &str) -> bool {
+    let bytes = date.as_bytes();
+    let number = |range: std::ops::Range<usize>| {
+        date.get(range)
+            .filter(|digits| digits.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|digits| digits.parse::<u32>().ok())
+    };
+    if bytes.len() != 10 || bytes[4] != b'-' || bytes[7] != b'-' {
+        return false;
+    }
+    let (Some(year), Some(month), Some(day)) = (number(0..4), number(5..7), number(8..10)) else {
+        return false;
+    };
+    let is_leap_year = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year => 29,
+        2 => 28,
+        _ => return false,
+    };
+    (1..=days).contains(&day)
+}
+
+/// A gemlog page ready to be written as a feed
+#[derive(Debug, Clone, PartialEq)]
+struct Gemlog {
+    title: String,
+    /// Absolute url of the html page
+    url: String,
+    /// Newest entries first
+    entries: Vec<Entry>,
+}
+
+/// An entry of a feed
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    title: String,
+    /// Absolute url
+    url: String,
+    date: String,
+    /// Html of the post, for local gemini files in full content mode
+    content: Option<String>,
+    /// First paragraph of the post, for local gemini files in summary mode
+    summary: Option<String>,
+}
+
+/// Write the feeds of the gemlog page at the root of the target directory
+/// `generated` is a page generated during the build, with its path, used instead of the source
+/// entries which can't be read are reported in `errors`, without content,
+/// the ones linking to excluded files, like drafts, are left out
+pub(crate) fn write_feeds(
+    source_tree_directory: &Path,
+    target_tree_directory: &Path,
+    feed: &FeedOptions,
+    generated: Option<(&Path, &str)>,
+    filter: &SourceFilter,
+    options: &Options,
+    errors: &mut Vec<Gemini2HtmlError>,
+) {
+    let gemlog = match read_gemlog(
+        source_tree_directory,
+        feed,
+        generated,
+        filter,
+        options,
+        errors,
+    ) {
+        Ok(gemlog) => gemlog,
+        Err(e) => {
+            errors.push(e);
+            return;
+        }
+    };
+    if gemlog.entries.is_empty() {
+        warn!(
+            "no dated link found in {:?}, the feeds are empty",
+            feed.source
+        );
+    }
+    if feed.atom {
+        let path = target_tree_directory.join(ATOM_FILE_NAME);
+        info!("- 📰 write atom feed {:?}", path);
+        if let Err(e) = write_to_file(&path, &atom(&gemlog, options)) {
+            errors.push(e);
+        }
+    }
+    if feed.rss {
+        let path = target_tree_directory.join(RSS_FILE_NAME);
+        info!("- 📰 write rss feed {:?}", path);
+        if let Err(e) = write_to_file(&path, &rss(&gemlog, options)) {
+            errors.push(e);
+        }
+    }
+}
+
+/// Read the gemlog page and the posts it links to
+fn read_gemlog(
+    source_tree_directory: &Path,
+    feed: &FeedOptions,
+    generated: Option<(&Path, &str)>,
+    filter: &SourceFilter,
+    options: &Options,
+    errors: &mut Vec<Gemini2HtmlError>,
+) -> Result<Gemlog> {
+    let page = source_tree_directory.join(&feed.source);
+    let Some(base_url) = options.base_url.as_deref() else {
+        return Err(Gemini2HtmlError::Feed {
+            path: page,
+            message: "feeds need the `base_url` of the site".to_string(),
+        });
+    };
+    let content = match generated {
+        Some((path, gemtext)) if path == feed.source => gemtext.to_string(),
+        _ => read_from_file(&page)?,
+    };
+    let (parsed_gemini, title) = parse_gemini(&content);
+    let directory = feed.source.parent().unwrap_or(Path::new(""));
+    let mut dated_links = dated_links(&parsed_gemini);
+    // drafts and ignored posts are not published, their entries neither
+    dated_links.retain(|dated_link| {
+        local_link_path(&dated_link.url).is_none_or(|link_path| {
+            let relative_path = normalize(&resolve_local_link(Path::new(""), directory, link_path));
+            !filter.is_excluded(&relative_path, false)
+        })
+    });
+    // dates sort as text, newest first
+    dated_links.sort_by(|a, b| b.date.cmp(&a.date));
+    if let Some(limit) = feed.limit {
+        dated_links.truncate(limit);
+    }
+    let entries = dated_links
+        .into_iter()
+        .map(|dated_link| {
+            let Some(link_path) = local_link_path(&dated_link.url) else {
+                // a post elsewhere, only its link can be given
+                return Entry {
+                    title: dated_link.title,
+                    url: dated_link.url,
+                    date: dated_link.date,
+                    content: None,
+                    summary: None,
+                };
+            };
+            let relative_path = normalize(&resolve_local_link(Path::new(""), directory, link_path));
+            let is_gemini = relative_path
+                .extension()
+                .is_some_and(|extension| extension == OsStr::new(&options.gemini_extension));
+            let post = match is_gemini {
+                true => match read_from_file(&source_tree_directory.join(&relative_path)) {
+                    Ok(post) => Some(parse_gemini(&post).0),
+                    Err(e) => {
+                        errors.push(e);
+                        None
+                    }
+                },
+                false => None,
+            };
+            let (content, summary) = match (post, feed.content) {
+                (Some(post), FeedContent::Full) => {
+                    let post = absolute_links(post, &relative_path, base_url, options);
+                    (Some(html_content(post, options)), None)
+                }
+                (Some(post), FeedContent::Summary) => (None, summary(&post)),
+                (None, _) => (None, None),
+            };
+            let html_path = match is_gemini {
+                true => relative_path.with_extension("html"),
+                false => relative_path,
+            };
+            Entry {
+                title: dated_link.title,
+                url: absolute_url(base_url, &html_path),
+                date: dated_link.date,
+                content,
+                summary,
+            }
+        })
+        .collect();
+    Ok(Gemlog {
+        title: title
+            .or(options.site_name.as_deref())
+            .unwrap_or("gemlog")
+            .to_string(),
+        url: absolute_url(base_url, &feed.source.with_extension("html")),
+        entries,
+    })
+}
+
+/// Make the local links of a post absolute, from the path of its gemini file :
+/// RSS has no `xml:base`, and feed readers don't know where the post comes from
+fn absolute_links(
+    post: Vec<ParsedGemini>,
+    page: &Path,
+    base_url: &str,
+    options: &Options,
+) -> Vec<ParsedGemini> {
+    let directory = page.parent().unwrap_or(Path::new(""));
+    post.into_iter()
+        .map(|line| {
+            let ParsedGemini::Link(link) = &line else {
+                return line;
+            };
+            let (url, description) = split_link(link);
+            let absolute = match local_link_path(url) {
+                Some(link_path) => {
+                    let path = normalize(&resolve_local_link(Path::new(""), directory, link_path));
+                    // gemini files are published as html
+                    let path = match path.extension() == Some(OsStr::new(&options.gemini_extension))
+                    {
+                        true => path.with_extension("html"),
+                        false => path,
+                    };
+                    // the query or the fragment are kept
+                    format!(
+                        "{}{}",
+                        absolute_url(base_url, &path),
+                        &url[link_path.len()..]
+                    )
+                }
+                None if url.starts_with('#') => {
+                    format!(
+                        "{}{url}",
+                        absolute_url(base_url, &page.with_extension("html"))
+                    )
+                }
+                None => return line,
+            };
+            // the text of the link stays the one of the post
+            ParsedGemini::Link(format!("{absolute} {}", description.unwrap_or(url)))
+        })
+        .collect()
+}
+
+/// First paragraph of a post
+fn summary(parsed_gemini: &[ParsedGemini]) -> Option<String> {
+    parsed_gemini.iter().find_map(|line| match line {
+        ParsedGemini::Text(text) if !text.trim().is_empty() => Some(text.trim().to_string()),
+        _ => None,
+    })
+}
+
+/// Remove `.` and `..` from a relative path, without touching the filesystem
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            _ => (),
+        }
+    }
+    normalized
+}
+
+/// Url of a file of the site
+pub(crate) fn absolute_url(base_url: &str, relative_path: &Path) -> String {
+    let path: Vec<String> = relative_path
+        .components()
+        .map(|component| percent_encode(&component.as_os_str().to_string_lossy()))
+        .collect();
+    format!("{}/{}", base_url.trim_end_matches('/'), path.join("/"))
+}
+
+/// The Atom feed of a gemlog, see RFC 4287
+fn atom(gemlog: &Gemlog, options: &Options) -> String {
+    let base_url = options.base_url.as_deref().unwrap_or_default();
+    let author = options
+        .author
+        .as_deref()
+        .or(options.site_name.as_deref())
+        .unwrap_or("unknown");
+    let updated = gemlog
+        .entries
+        .first()
+        .map_or("1970-01-01", |entry| entry.date.as_str());
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\">\n  <title>{}</title>\n  <link href=\"{}\" />\n  <link rel=\"self\" href=\"{}\" />\n  <id>{}</id>\n  <updated>{updated}T00:00:00Z</updated>\n  <author><name>{}</name></author>\n",
+        escaped_attribute(&gemlog.title),
+        escaped_attribute(&gemlog.url),
+        escaped_attribute(&absolute_url(base_url, Path::new(ATOM_FILE_NAME))),
+        escaped_attribute(&gemlog.url),
+        escaped_attribute(author)
+    );
+    for entry in &gemlog.entries {
+        let url = escaped_attribute(&entry.url);
+        xml.push_str(&format!(
+            "  <entry>\n    <title>{}</title>\n    <link href=\"{url}\" />\n    <id>{url}</id>\n    <updated>{}T00:00:00Z</updated>\n",
+            escaped_attribute(&entry.title),
+            entry.date
+        ));
+        // relative links of the post are relative to its url
+        if let Some(content) = &entry.content {
+            xml.push_str(&format!(
+                "    <content type=\"html\" xml:base=\"{url}\">{}</content>\n",
+                escaped_attribute(content)
+            ));
+        }
+        if let Some(summary) = &entry.summary {
+            xml.push_str(&format!(
+                "    <summary>{}</summary>\n",
+                escaped_attribute(summary)
+            ));
+        }
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+/// The RSS 2.0 feed of a gemlog
+fn rss(gemlog: &Gemlog, options: &Options) -> String {
+    let base_url = options.base_url.as_deref().unwrap_or_default();
+    let title = escaped_attribute(&gemlog.title);
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\">\n<channel>\n  <title>{title}</title>\n  <link>{}</link>\n  <description>{title}</description>\n  <atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\" />\n",
+        escaped_attribute(&gemlog.url),
+        escaped_attribute(&absolute_url(base_url, Path::new(RSS_FILE_NAME)))
+    );
+    if let Some(language) = &options.language {
+        xml.push_str(&format!(
+            "  <language>{}</language>\n",
+            escaped_attribute(language)
+        ));
+    }
+    for entry in &gemlog.entries {
+        let url = escaped_attribute(&entry.url);
+        xml.push_str(&format!(
+            "  <item>\n    <title>{}</title>\n    <link>{url}</link>\n    <guid>{url}</guid>\n    <pubDate>{}</pubDate>\n",
+            escaped_attribute(&entry.title),
+            rfc822_date(&entry.date)
+        ));
+        // html content and text summary are both escaped in the description
+        if let Some(description) = entry.content.as_ref().or(entry.summary.as_ref()) {
+            xml.push_str(&format!(
+                "    <description>{}</description>\n",
+                escaped_attribute(description)
+            ));
+        }
+        xml.push_str("  </item>\n");
+    }
+    xml.push_str("</channel>\n</rss>\n");
+    xml
+}
+
+/// A `YYYY-MM-DD` date at midnight UTC, in the RFC 822 format of RSS
+fn rfc822_date(date: &str) -> String {
+    let number = |range: std::ops::Range<usize>| {
+        date.get(range)
+            .and_then(|digits| digits.parse::<i64>().ok())
+            .unwrap_or(1)
+    };
+    let (year, month, day) = (number(0..4), number(5..7), number(8..10));
+    // civil date to days, see http://howardhinnant.github.io/date_algorithms.html
+    let shifted_year = if month <= 2 { year - 1 } else { year };
+    let era = shifted_year.div_euclid(400);
+    let year_of_era = shifted_year.rem_euclid(400);
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    // 1970-01-01 was a thursday
+    let weekday = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"][days.rem_euclid(7) as usize];
+    let month_name = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ][(month.clamp(1, 12) - 1) as usize];
+    format!("{weekday}, {day:02} {month_name} {year:04} 00:00:00 +0000")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_tree::TestTree;
+    use std::fs;
+
+    #[test]
+    fn test_dated_links() {
+        let (parsed_gemini, _) = parse_gemini(
+            "# Gemlog\n=> 2026-10-01-post.gmi 2026-10-01 - First post\n=> about.gmi About\n=> gemini://fqdn/x.gmi 2026-13-01 not a date\n=> old.gmi 2025-01-31\n",
+        );
+        assert_eq!(
+            dated_links(&parsed_gemini),
+            vec![
+                DatedLink {
+                    url: "2026-10-01-post.gmi".to_string(),
+                    date: "2026-10-01".to_string(),
+                    title: "First post".to_string(),
+                },
+                DatedLink {
+                    url: "old.gmi".to_string(),
+                    date: "2025-01-31".to_string(),
+                    title: "2025-01-31".to_string(),
+                },
+            ]
+        );
+    }
+    #[test]
+    fn test_is_date() {
+        assert!(is_date("2026-10-01"));
+        assert!(!is_date("2026-00-01"));
+        assert!(!is_date("2026-1-01x"));
+        assert!(!is_date("26-10-01"));
+        assert!(!is_date("2026-10-0é"));
+        assert!(!is_date("2026-10-32"));
+        // days are checked against the length of the month
+        assert!(is_date("2024-02-29"));
+        assert!(is_date("2000-02-29"));
+        assert!(!is_date("2026-02-29"));
+        assert!(!is_date("1900-02-29"));
+        assert!(!is_date("2024-02-30"));
+        assert!(!is_date("2026-04-31"));
+        assert!(is_date("2026-12-31"));
+    }
+    #[test]
+    fn test_absolute_url() {
+        assert_eq!(
+            absolute_url("https://example.org/", Path::new("gemlog/a post.html")),
+            "https://example.org/gemlog/a%20post.html"
+        );
+        assert_eq!(
+            absolute_url("https://example.org/~me", Path::new("atom.xml")),
+            "https://example.org/~me/atom.xml"
+        );
+        assert_eq!(
+            normalize(Path::new("gemlog/../posts/./a.gmi")),
+            Path::new("posts/a.gmi")
+        );
+    }
+    #[test]
+    fn test_write_atom_feed() {
+        let source_tree_directory = TestTree::new("feed_source");
+        let target_tree_directory = TestTree::new("feed_target");
+        fs::create_dir(source_tree_directory.join("gemlog")).expect("unable to create test dir");
+        fs::write(
+            source_tree_directory.join("gemlog/index.gmi"),
+            "# My <gemlog>\n=> 2026-09-01-old.gmi 2026-09-01 Old\n=> 2026-10-01-new.gmi 2026-10-01 New & shiny\n=> gemini://elsewhere/post.gmi 2026-09-15 Elsewhere\n=> 2026-10-15-wip.draft.gmi 2026-10-15 Work in progress\n",
+        )
+        .expect("unable to write test file");
+        for (name, content) in [
+            ("gemlog/2026-10-01-new.gmi", "# New\nsome <text>\n"),
+            ("gemlog/2026-10-15-wip.draft.gmi", "# Not published\n"),
+        ] {
+            fs::write(source_tree_directory.join(name), content)
+                .expect("unable to write test file");
+        }
+        let options = Options {
+            base_url: Some("https://example.org/".to_string()),
+            author: Some("me".to_string()),
+            ..Options::default()
+        };
+        let feed = FeedOptions {
+            source: PathBuf::from("gemlog/index.gmi"),
+            ..FeedOptions::default()
+        };
+        let mut errors = Vec::new();
+        write_feeds(
+            &source_tree_directory,
+            &target_tree_directory,
+            &feed,
+            None,
+            &SourceFilter::default(),
+            &options,
+            &mut errors,
+        );
+        // the old post does not exist
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].path(),
+            Some(
+                source_tree_directory
+                    .join("gemlog/2026-09-01-old.gmi")
+                    .as_path()
+            )
+        );
+        let xml = fs::read_to_string(target_tree_directory.join(ATOM_FILE_NAME)).unwrap();
+        assert!(xml.contains("<title>My &lt;gemlog&gt;</title>"));
+        assert!(xml.contains("<link rel=\"self\" href=\"https://example.org/atom.xml\" />"));
+        assert!(xml.contains("<id>https://example.org/gemlog/index.html</id>"));
+        assert!(xml.contains(
+            "<updated>2026-10-01T00:00:00Z</updated>\n  <author><name>me</name></author>"
+        ));
+        // newest first, local posts are linked to their html and embedded
+        let new = xml.find("<title>New &amp; shiny</title>").unwrap();
+        let elsewhere = xml
+            .find("<link href=\"gemini://elsewhere/post.gmi\" />")
+            .unwrap();
+        let old = xml
+            .find("https://example.org/gemlog/2026-09-01-old.html")
+            .unwrap();
+        assert!(new < elsewhere && elsewhere < old);
+        // drafts linked from the gemlog page are left out
+        assert!(!xml.contains("Work in progress"));
+        assert!(!xml.contains("Not published"));
+        assert!(xml.contains(
+            "<content type=\"html\" xml:base=\"https://example.org/gemlog/2026-10-01-new.html\">&lt;h1&gt;New&lt;/h1&gt;\n&lt;p&gt;some &amp;lt;text&amp;gt;&lt;/p&gt;\n</content>"
+        ));
+    }
+    #[test]
+    fn test_rfc822_date() {
+        assert_eq!(rfc822_date("1970-01-01"), "Thu, 01 Jan 1970 00:00:00 +0000");
+        assert_eq!(rfc822_date("2026-10-01"), "Thu, 01 Oct 2026 00:00:00 +0000");
+        assert_eq!(rfc822_date("2024-02-29"), "Thu, 29 Feb 2024 00:00:00 +0000");
+        assert_eq!(rfc822_date("2000-03-01"), "Wed, 01 Mar 2000 00:00:00 +0000");
+    }
+    #[test]
+    fn test_write_rss_feed_with_summaries() {
+        let source_tree_directory = TestTree::new("rss_source");
+        let target_tree_directory = TestTree::new("rss_target");
+        fs::write(
+            source_tree_directory.join("index.gmi"),
+            "# Gemlog\n=> a.gmi 2026-01-01 A\n=> c.gmi 2026-03-01 C\n=> b.gmi 2026-02-01 B\n",
+        )
+        .expect("unable to write test file");
+        for post in ["a", "b", "c"] {
+            fs::write(
+                source_tree_directory.join(format!("{post}.gmi")),
+                format!("# {post}\n\nfirst <{post}>\n\nsecond\n"),
+            )
+            .expect("unable to write test file");
+        }
+        let options = Options {
+            base_url: Some("https://example.org".to_string()),
+            ..Options::default()
+        };
+        let feed = FeedOptions {
+            atom: false,
+            rss: true,
+            limit: Some(2),
+            content: FeedContent::Summary,
+            ..FeedOptions::default()
+        };
+        let mut errors = Vec::new();
+        write_feeds(
+            &source_tree_directory,
+            &target_tree_directory,
+            &feed,
+            None,
+            &SourceFilter::default(),
+            &options,
+            &mut errors,
+        );
+        assert!(errors.is_empty());
+        assert!(!target_tree_directory.join(ATOM_FILE_NAME).exists());
+        let xml = fs::read_to_string(target_tree_directory.join(RSS_FILE_NAME)).unwrap();
+        assert!(
+            xml.starts_with("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<rss version=\"2.0\"")
+        );
+        assert!(xml.contains("<atom:link href=\"https://example.org/rss.xml\" rel=\"self\" type=\"application/rss+xml\" />"));
+        // the two newest posts, with their first paragraph
+        assert_eq!(xml.matches("<item>").count(), 2);
+        let c = xml.find("<guid>https://example.org/c.html</guid>").unwrap();
+        let b = xml.find("<guid>https://example.org/b.html</guid>").unwrap();
+        assert!(c < b);
+        assert!(!xml.contains("a.html"));
+        assert!(xml.contains("<pubDate>Sun, 01 Mar 2026 00:00:00 +0000</pubDate>"));
+        assert!(xml.contains("<description>first &lt;c&gt;</description>"));
+        assert!(!xml.contains("second"));
+    }
+    #[test]
+    fn test_rss_links_are_absolute() {
+        let source_tree_directory = TestTree::new("rss_links_source");
+        let target_tree_directory = TestTree::new("rss_links_target");
+        fs::create_dir(source_tree_directory.join("gemlog")).expect("unable to create test dir");
+        for (name, content) in [
+            (
+                "gemlog/index.gmi",
+                "# Gemlog\n=> post.gmi 2026-10-01 Post\n",
+            ),
+            (
+                "gemlog/post.gmi",
+                "=> other.gmi\n=> ../images/a.png An image\n=> /about.gmi#me Me\n=> #top Top\n=> https://elsewhere/ Elsewhere\n",
+            ),
+        ] {
+            fs::write(source_tree_directory.join(name), content)
+                .expect("unable to write test file");
+        }
+        let options = Options {
+            base_url: Some("https://example.org".to_string()),
+            ..Options::default()
+        };
+        let feed = FeedOptions {
+            source: PathBuf::from("gemlog/index.gmi"),
+            atom: false,
+            rss: true,
+            ..FeedOptions::default()
+        };
+        let mut errors = Vec::new();
+        write_feeds(
+            &source_tree_directory,
+            &target_tree_directory,
+            &feed,
+            None,
+            &SourceFilter::default(),
+            &options,
+            &mut errors,
+        );
+        assert!(errors.is_empty(), "{errors:?}");
+        let xml = fs::read_to_string(target_tree_directory.join(RSS_FILE_NAME)).unwrap();
+        for url in [
+            "https://example.org/gemlog/other.html",
+            "https://example.org/images/a.png",
+            "https://example.org/about.html#me",
+            "https://example.org/gemlog/post.html#top",
+            "https://elsewhere/",
+        ] {
+            assert!(
+                xml.contains(&format!("href=&quot;{url}&quot;")),
+                "{url} not in {xml}"
+            );
+        }
+        assert!(xml.contains("src=&quot;https://example.org/images/a.png&quot;"));
+        // the text of a link without description is unchanged
+        assert!(xml.contains("&gt;other.gmi&lt;/a&gt;"));
+    }
+    #[test]
+    fn test_feed_needs_base_url() {
+        let mut errors = Vec::new();
+        write_feeds(
+            Path::new("./tests"),
+            Path::new("./not_written"),
+            &FeedOptions::default(),
+            None,
+            &SourceFilter::default(),
+            &Options::default(),
+            &mut errors,
+        );
+        assert!(matches!(errors[..], [Gemini2HtmlError::Feed { .. }]));
+    }
+}
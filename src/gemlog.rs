@@ -0,0 +1,350 @@
+//! Gemlog index generation
+//!
+//! Posts are the gemini files of a directory named after their date,
+//! like `2026-10-01-my-post.gmi`. Their index is generated at each build,
+//! newest first and grouped by year, with dated links so it can be used for feeds.
+//!
+//! The gemtext index is written in the capsule, next to the posts, and its html
+//! in the target directory. A hand-written index is never replaced.
+
+use crate::feed::is_date;
+use crate::ignore::SourceFilter;
+use crate::site::percent_encode;
+use crate::template::Source;
+use crate::{
+    Gemini2HtmlError, Operation, Options, ParsedGemini, page_to_html, parse_gemini, read_from_file,
+    split_link, write_to_file,
+};
+use log::info;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the generated index, in the posts directory of the capsule
+pub const INDEX_FILE_NAME: &str = "index.gmi";
+
+/// Generation of the gemlog index
+#[derive(Debug, Clone, PartialEq)]
+pub struct GemlogOptions {
+    /// Directory of the posts, relative to the root of the capsule
+    pub posts: PathBuf,
+    /// Title of the index
+    pub title: String,
+}
+
+impl Default for GemlogOptions {
+    fn default() -> Self {
+        GemlogOptions {
+            posts: PathBuf::from("gemlog"),
+            title: "Gemlog".to_string(),
+        }
+    }
+}
+
+impl GemlogOptions {
+    /// Path of the generated index, relative to the root of the capsule
+    pub fn index(&self) -> PathBuf {
+        self.posts.join(INDEX_FILE_NAME)
+    }
+}
+
+/// A post of the gemlog
+#[derive(Debug, Clone, PartialEq)]
+struct Post {
+    file_name: String,
+    /// `YYYY-MM-DD`, from the file name
+    date: String,
+    title: String,
+}
+
+/// True if the index of the capsule can be generated : it does not exist yet,
+/// or it only has the lines of a generated index
+pub(crate) fn is_generated_index(source_tree_directory: &Path, gemlog: &GemlogOptions) -> bool {
+    match fs::read_to_string(source_tree_directory.join(gemlog.index())) {
+        Ok(content) => looks_generated(&content),
+        Err(e) => e.kind() == io::ErrorKind::NotFound,
+    }
+}
+
+/// True if a gemtext only has a title, year headings and dated links, like [`index_gemtext`]
+fn looks_generated(gemtext: &str) -> bool {
+    let mut lines = gemtext.lines().filter(|line| !line.is_empty());
+    let is_year = |line: &str| {
+        line.strip_prefix("## ")
+            .is_some_and(|year| year.len() == 4 && year.bytes().all(|c| c.is_ascii_digit()))
+    };
+    let is_dated_link = |line: &str| {
+        line.strip_prefix("=> ").is_some_and(|link| {
+            split_link(link)
+                .1
+                .and_then(|description| description.get(..10))
+                .is_some_and(is_date)
+        })
+    };
+    lines.next().is_none_or(|title| title.starts_with("# "))
+        && lines.all(|line| is_year(line) || is_dated_link(line))
+}
+
+/// Write the gemlog index as gemtext in the posts directory of the capsule,
+/// if it changed, and as html in the target
+/// the caller checks it is not a hand-written index, see [`is_generated_index`]
+/// return the gemtext, `None` if it can't be built
+pub(crate) fn write_index(
+    source_tree_directory: &Path,
+    target_tree_directory: &Path,
+    gemlog: &GemlogOptions,
+    filter: &SourceFilter,
+    options: &Options,
+    errors: &mut Vec<Gemini2HtmlError>,
+) -> Option<String> {
+    let posts = find_posts(source_tree_directory, gemlog, filter, options, errors)?;
+    info!("- 📚 gemlog index of {} post(s)", posts.len());
+    let gemtext = index_gemtext(&gemlog.title, &posts);
+    let index = gemlog.index();
+    let source = Source {
+        path: Some(&index),
+        last_modified: None,
+    };
+    let html = page_to_html(&gemtext, &source, options).html;
+    // an unchanged index is not written again, watch would see it
+    let gemtext_path = source_tree_directory.join(&index);
+    if fs::read_to_string(&gemtext_path).ok().as_ref() != Some(&gemtext)
+        && let Err(e) = write_to_file(&gemtext_path, &gemtext)
+    {
+        errors.push(e);
+    }
+    if let Err(e) = write_to_file(
+        &target_tree_directory.join(index.with_extension("html")),
+        &html,
+    ) {
+        errors.push(e);
+    }
+    Some(gemtext)
+}
+
+/// Dated gemini files of the posts directory, newest first
+/// drafts and ignored files are not posts
+fn find_posts(
+    source_tree_directory: &Path,
+    gemlog: &GemlogOptions,
+    filter: &SourceFilter,
+    options: &Options,
+    errors: &mut Vec<Gemini2HtmlError>,
+) -> Option<Vec<Post>> {
+    let posts_directory = &source_tree_directory.join(&gemlog.posts);
+    let tree = match fs::read_dir(posts_directory) {
+        Ok(tree) => tree,
+        Err(e) => {
+            errors.push(Gemini2HtmlError::io(Operation::ReadDir, posts_directory)(e));
+            return None;
+        }
+    };
+    let mut posts = Vec::new();
+    for direntry in tree {
+        let path = match direntry {
+            Ok(direntry) => direntry.path(),
+            Err(e) => {
+                errors.push(Gemini2HtmlError::io(Operation::ReadDir, posts_directory)(e));
+                continue;
+            }
+        };
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let Some(date) = file_name.get(..10).filter(|date| is_date(date)) else {
+            continue;
+        };
+        if path.extension() != Some(OsStr::new(&options.gemini_extension))
+            || !path.is_file()
+            || filter.is_excluded(&gemlog.posts.join(&*file_name), false)
+        {
+            continue;
+        }
+        let content = match read_from_file(&path) {
+            Ok(content) => content,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        };
+        let (parsed_gemini, _) = parse_gemini(&content);
+        let title = parsed_gemini
+            .iter()
+            .find_map(|line| match line {
+                ParsedGemini::Heading1(heading) => Some(heading.clone()),
+                _ => None,
+            })
+            .unwrap_or_else(|| title_from_file_name(&path));
+        posts.push(Post {
+            file_name: file_name.to_string(),
+            date: date.to_string(),
+            title,
+        });
+    }
+    // dates sort as text, the file name breaks ties
+    posts.sort_by(|a, b| (&b.date, &b.file_name).cmp(&(&a.date, &a.file_name)));
+    Some(posts)
+}
+
+/// Title of a post without heading : its file name, without date and extension
+fn title_from_file_name(path: &Path) -> String {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let title = stem[10..]
+        .trim_start_matches(['-', '_'])
+        .replace(['-', '_'], " ");
+    match title.is_empty() {
+        true => stem[..10].to_string(),
+        false => title,
+    }
+}
+
+/// The gemtext of the index, a section per year
+fn index_gemtext(title: &str, posts: &[Post]) -> String {
+    let mut gemtext = format!("# {title}\n");
+    let mut year = "";
+    for post in posts {
+        if post.date[..4] != *year {
+            year = &post.date[..4];
+            gemtext.push_str(&format!("\n## {year}\n\n"));
+        }
+        gemtext.push_str(&format!(
+            "=> {} {} {}\n",
+            percent_encode(&post.file_name),
+            post.date,
+            post.title
+        ));
+    }
+    gemtext
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_tree::TestTree;
+
+    #[test]
+    fn test_index_gemtext() {
+        let post = |file_name: &str, title: &str| Post {
+            file_name: file_name.to_string(),
+            date: file_name[..10].to_string(),
+            title: title.to_string(),
+        };
+        let posts = [
+            post("2026-10-01-b.gmi", "B"),
+            post("2026-01-01-a.gmi", "A"),
+            post("2025-12-31-z.gmi", "Z"),
+        ];
+        assert_eq!(
+            index_gemtext("Gemlog", &posts),
+            "# Gemlog\n\n## 2026\n\n=> 2026-10-01-b.gmi 2026-10-01 B\n=> 2026-01-01-a.gmi 2026-01-01 A\n\n## 2025\n\n=> 2025-12-31-z.gmi 2025-12-31 Z\n"
+        );
+    }
+    #[test]
+    fn test_title_from_file_name() {
+        assert_eq!(
+            title_from_file_name(Path::new("2026-10-01-my_first-post.gmi")),
+            "my first post"
+        );
+        assert_eq!(
+            title_from_file_name(Path::new("2026-10-01.gmi")),
+            "2026-10-01"
+        );
+    }
+    #[test]
+    fn test_write_index() {
+        let source_tree_directory = TestTree::new("gemlog_source");
+        let target_tree_directory = TestTree::new("gemlog_target");
+        for directory in [&source_tree_directory, &target_tree_directory] {
+            fs::create_dir(directory.join("posts")).expect("unable to create test directory");
+        }
+        let posts = [
+            ("2025-06-01-old.gmi", "# Old post\ntext\n# second heading\n"),
+            ("2026-10-01-new-one.gmi", "no heading\n"),
+            ("2026-09-01 spaced out.gmi", "# Spaced out\n"),
+            ("about.gmi", "# not a post\n"),
+            ("2026-05-01-wip.draft.gmi", "# a draft\n"),
+            ("2026-02-01-image.png", ""),
+        ];
+        for (name, content) in posts {
+            fs::write(source_tree_directory.join("posts").join(name), content)
+                .expect("unable to write test file");
+        }
+        let gemlog = GemlogOptions {
+            posts: PathBuf::from("posts"),
+            ..GemlogOptions::default()
+        };
+        let mut errors = Vec::new();
+        let gemtext = write_index(
+            &source_tree_directory,
+            &target_tree_directory,
+            &gemlog,
+            &SourceFilter::default(),
+            &Options::default(),
+            &mut errors,
+        )
+        .unwrap();
+        assert!(errors.is_empty());
+        assert_eq!(
+            gemtext,
+            "# Gemlog\n\n## 2026\n\n=> 2026-10-01-new-one.gmi 2026-10-01 new one\n=> 2026-09-01%20spaced%20out.gmi 2026-09-01 Spaced out\n\n## 2025\n\n=> 2025-06-01-old.gmi 2025-06-01 Old post\n"
+        );
+        // the gemtext is written in the capsule, the html in the target
+        assert_eq!(
+            fs::read_to_string(source_tree_directory.join("posts/index.gmi")).unwrap(),
+            gemtext
+        );
+        assert!(!target_tree_directory.join("posts/index.gmi").exists());
+        assert!(is_generated_index(&source_tree_directory, &gemlog));
+        let html = fs::read_to_string(target_tree_directory.join("posts/index.html")).unwrap();
+        assert!(html.contains(
+            "<h2>2026</h2>\n<p></p>\n<a href=\"2026-10-01-new-one.html\">2026-10-01 new one</a><br />"
+        ));
+        assert!(html.contains(
+            "<a href=\"2026-09-01%20spaced%20out.html\">2026-09-01 Spaced out</a><br />"
+        ));
+        // a missing posts directory is an error
+        let gemlog = GemlogOptions::default();
+        assert!(
+            write_index(
+                &source_tree_directory,
+                &target_tree_directory,
+                &gemlog,
+                &SourceFilter::default(),
+                &Options::default(),
+                &mut errors,
+            )
+            .is_none()
+        );
+        assert_eq!(errors.len(), 1);
+    }
+    #[test]
+    fn test_is_generated_index() {
+        let source_tree_directory = TestTree::new("generated_index");
+        fs::create_dir(source_tree_directory.join("posts"))
+            .expect("unable to create test directory");
+        let gemlog = GemlogOptions {
+            posts: PathBuf::from("posts"),
+            ..GemlogOptions::default()
+        };
+        // a missing index can be generated
+        assert!(is_generated_index(&source_tree_directory, &gemlog));
+        for (content, expected) in [
+            ("", true),
+            ("# Gemlog\n", true),
+            (
+                "# Gemlog\n\n## 2026\n\n=> 2026-10-01-new.gmi 2026-10-01 new\n",
+                true,
+            ),
+            ("# My posts\n\nWelcome!\n", false),
+            ("# Gemlog\n\n=> about.gmi About me\n", false),
+            ("=> 2026-10-01-new.gmi 2026-10-01 new\n", false),
+        ] {
+            fs::write(source_tree_directory.join(gemlog.index()), content)
+                .expect("unable to write test file");
+            assert_eq!(
+                is_generated_index(&source_tree_directory, &gemlog),
+                expected,
+                "{content:?}"
+            );
+        }
+    }
+}
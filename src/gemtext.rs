@@ -0,0 +1,506 @@
+//! Gemtext parser and document model
+//!
+//! The parser reads a gemtext document line by line and produces a flat list of
+//! `ParsedGemini` elements, which is what the html renderer eats. The public
+//! [`Document`] groups those elements into [`Block`]s, so other tools (linters,
+//! indexers, other renderers...) can reuse the very same parser.
+//!
+//! see <https://geminiprotocol.net/docs/gemtext-specification.gmi> for details
+
+/// Gemini syntax elements, as produced by the parser
+/// for lists and preformatted text, flags elements are used for beginning and end tags
+pub(crate) enum ParsedGemini {
+    Link(String),
+    Heading1(String),
+    Heading2(String),
+    Heading3(String),
+    ListStart,
+    ListEnd,
+    ListElement(String),
+    QuoteStart,
+    QuoteEnd,
+    Quote(String),
+    Text(String),
+    /// Alt text of the opening toggle line
+    PreformattedStart(Option<String>),
+    /// Alt text of the block being closed, the renderer may need it for closing tags
+    PreformattedEnd(Option<String>),
+    PreformattedText(String),
+}
+
+/// A parsed gemtext document
+///
+/// More fields may be added, build one with [`parse`] or `Document::default()`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Document {
+    /// Title of the document, taken from a level 1 heading
+    pub title: Option<String>,
+    /// Blocks of the document, in order of appearance
+    pub blocks: Vec<Block>,
+}
+
+/// A block of a gemtext document
+///
+/// Single line elements (text, links, headings) are blocks of their own,
+/// consecutive list items and quote lines are grouped together,
+/// new kinds of blocks may be added, matches need a wildcard arm
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Block {
+    /// A text line, may be empty
+    Text(String),
+    /// A link line `=> url [description]`
+    Link(Link),
+    /// A heading line, `level` is 1, 2 or 3
+    Heading { level: u8, text: String },
+    /// Consecutive list items `* item`
+    List(Vec<String>),
+    /// Consecutive quote lines `> quote`
+    Quote(Vec<String>),
+    /// A preformatted block, between two toggle lines
+    Preformatted(Preformatted),
+}
+
+/// A gemtext link
+///
+/// More fields may be added, build one with [`Link::parse`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Link {
+    /// Target of the link, as written : absolute, or relative to the document
+    pub url: String,
+    /// Text following the url, `None` if there is none
+    pub description: Option<String>,
+}
+
+/// A preformatted block, its lines are kept verbatim
+///
+/// More fields may be added, build one with [`parse`] or `Preformatted::default()`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Preformatted {
+    /// Alt text following the opening toggle line, if any
+    pub alt: Option<String>,
+    /// Lines between the toggle lines, without their line ending
+    pub lines: Vec<String>,
+}
+
+impl Link {
+    /// Split the content of a link line (after `=>`) in url and optional description
+    pub fn parse(link: &str) -> Link {
+        let (url, description) = split_link(link);
+        Link {
+            url: url.to_string(),
+            description: description.map(str::to_string),
+        }
+    }
+}
+
+/// Split a link in url and description, separated by spaces or tabs
+/// leading whitespaces are ignored
+pub(crate) fn split_link(link: &str) -> (&str, Option<&str>) {
+    // trim start before split or description will be lost
+    let link = link.trim_start_matches(is_whitespace);
+    match link.split_once(is_whitespace) {
+        Some((url, description)) => {
+            let description = description.trim_matches(is_whitespace);
+            (url, (!description.is_empty()).then_some(description))
+        }
+        None => (link, None),
+    }
+}
+
+impl Document {
+    /// Iterate over all links of the document
+    pub fn links(&self) -> impl Iterator<Item = &Link> {
+        self.blocks.iter().filter_map(|block| match block {
+            Block::Link(link) => Some(link),
+            _ => None,
+        })
+    }
+}
+
+impl From<Vec<ParsedGemini>> for Document {
+    /// Group parsed elements in blocks
+    fn from(parsed_gemini: Vec<ParsedGemini>) -> Self {
+        let mut document = Document::default();
+        for element in parsed_gemini {
+            match element {
+                ParsedGemini::Link(link) => document.blocks.push(Block::Link(Link::parse(&link))),
+                ParsedGemini::Heading1(text) => {
+                    document.title = Some(text.clone());
+                    document.blocks.push(Block::Heading { level: 1, text })
+                }
+                ParsedGemini::Heading2(text) => {
+                    document.blocks.push(Block::Heading { level: 2, text })
+                }
+                ParsedGemini::Heading3(text) => {
+                    document.blocks.push(Block::Heading { level: 3, text })
+                }
+                ParsedGemini::ListStart => document.blocks.push(Block::List(Vec::new())),
+                ParsedGemini::ListElement(item) => match document.blocks.last_mut() {
+                    Some(Block::List(items)) => items.push(item),
+                    _ => document.blocks.push(Block::List(vec![item])),
+                },
+                ParsedGemini::QuoteStart => document.blocks.push(Block::Quote(Vec::new())),
+                ParsedGemini::Quote(quote) => match document.blocks.last_mut() {
+                    Some(Block::Quote(quotes)) => quotes.push(quote),
+                    _ => document.blocks.push(Block::Quote(vec![quote])),
+                },
+                ParsedGemini::Text(text) => document.blocks.push(Block::Text(text)),
+                ParsedGemini::PreformattedStart(alt) => {
+                    document.blocks.push(Block::Preformatted(Preformatted {
+                        alt,
+                        lines: Vec::new(),
+                    }))
+                }
+                ParsedGemini::PreformattedText(line) => match document.blocks.last_mut() {
+                    Some(Block::Preformatted(preformatted)) => preformatted.lines.push(line),
+                    _ => document.blocks.push(Block::Preformatted(Preformatted {
+                        alt: None,
+                        lines: vec![line],
+                    })),
+                },
+                ParsedGemini::ListEnd
+                | ParsedGemini::QuoteEnd
+                | ParsedGemini::PreformattedEnd(_) => (),
+            }
+        }
+        document
+    }
+}
+
+/// Parse a gemtext document
+pub fn parse(gemini_content: &str) -> Document {
+    let (parsed_gemini, _title) = parse_gemini(gemini_content);
+    Document::from(parsed_gemini)
+}
+
+/// Whitespace, as defined by the gemtext specification : space or tab
+fn is_whitespace(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+/// Type of a line outside of a preformatted block
+/// the type only depends on the first characters of the line
+enum LineType<'a> {
+    Link(&'a str),
+    Heading(u8, &'a str),
+    ListItem(&'a str),
+    Quote(&'a str),
+    PreformatToggle(Option<&'a str>),
+    Text(&'a str),
+}
+
+/// Find the type of a line, following gemtext specification rules
+fn line_type(line: &str) -> LineType<'_> {
+    // `=>[<whitespace>]<url>[<whitespace><description>]`
+    // a link without url is just some text
+    if let Some(link) = line.strip_prefix("=>")
+        && !link.trim_matches(is_whitespace).is_empty()
+    {
+        LineType::Link(link)
+    // `` ``` `` toggles preformatted mode, the rest of the line is alt text
+    } else if let Some(alt) = line.strip_prefix("```") {
+        let alt = alt.trim_matches(is_whitespace);
+        LineType::PreformatToggle((!alt.is_empty()).then_some(alt))
+    // longest heading prefix first, `####` is a level 3 heading starting with `#`
+    } else if let Some(heading) = line.strip_prefix("###") {
+        LineType::Heading(3, heading.trim_start_matches(is_whitespace))
+    } else if let Some(heading) = line.strip_prefix("##") {
+        LineType::Heading(2, heading.trim_start_matches(is_whitespace))
+    } else if let Some(heading) = line.strip_prefix('#') {
+        LineType::Heading(1, heading.trim_start_matches(is_whitespace))
+    // the space is mandatory for list items, `*item` is a text line
+    } else if let Some(item) = line.strip_prefix("* ") {
+        LineType::ListItem(item)
+    // but optional for quotes
+    } else if let Some(quote) = line.strip_prefix('>') {
+        LineType::Quote(quote.trim_start_matches(is_whitespace))
+    } else {
+        LineType::Text(line)
+    }
+}
+
+/// Main course here, read a gemini content line by line
+/// if a syntax element is found, store the line in the corresponding enum
+/// for lists and preformatted text, use flags for beginning and end tags
+pub(crate) fn parse_gemini(gemini_content: &str) -> (Vec<ParsedGemini>, Option<&str>) {
+    // init the Vec returned
+    let mut parsed_gemini: Vec<ParsedGemini> = Vec::new();
+    let mut title: Option<&str> = None;
+    // create flags for listes, quotes and preformatted text
+    let mut flag_list = false;
+    let mut flag_quote = false;
+    let mut flag_preformatted = false;
+    // alt text of the current preformatted block
+    let mut preformatted_alt: Option<String> = None;
+    for line in gemini_content.lines() {
+        // in preformatted mode, only a toggle line is special, others are kept verbatim
+        if flag_preformatted {
+            // alt text is only meaningful on the opening line
+            if line.starts_with("```") {
+                parsed_gemini.push(ParsedGemini::PreformattedEnd(preformatted_alt.take()));
+                flag_preformatted = false;
+            } else {
+                parsed_gemini.push(ParsedGemini::PreformattedText(line.to_string()));
+            }
+            continue;
+        }
+        let line_type = line_type(line);
+
+        // if we were in a list, but not anymore, end it
+        if flag_list && !matches!(line_type, LineType::ListItem(_)) {
+            parsed_gemini.push(ParsedGemini::ListEnd);
+            flag_list = false;
+        }
+        // same for quotes
+        if flag_quote && !matches!(line_type, LineType::Quote(_)) {
+            parsed_gemini.push(ParsedGemini::QuoteEnd);
+            flag_quote = false;
+        }
+
+        // now push the content of the line in output Vec
+        match line_type {
+            LineType::Link(link) => parsed_gemini.push(ParsedGemini::Link(link.to_string())),
+            LineType::Heading(1, heading) => {
+                title = Some(heading);
+                parsed_gemini.push(ParsedGemini::Heading1(heading.to_string()))
+            }
+            LineType::Heading(2, heading) => {
+                parsed_gemini.push(ParsedGemini::Heading2(heading.to_string()))
+            }
+            LineType::Heading(_, heading) => {
+                parsed_gemini.push(ParsedGemini::Heading3(heading.to_string()))
+            }
+            // a list must begin with tag `<ul>` and start with `</ul>`
+            LineType::ListItem(item) => {
+                // begin the list
+                if !flag_list {
+                    parsed_gemini.push(ParsedGemini::ListStart);
+                    flag_list = true;
+                }
+                parsed_gemini.push(ParsedGemini::ListElement(item.to_string()));
+            }
+            // consecutive quotes are grouped in a single `<blockquote>`
+            LineType::Quote(quote) => {
+                if !flag_quote {
+                    parsed_gemini.push(ParsedGemini::QuoteStart);
+                    flag_quote = true;
+                }
+                parsed_gemini.push(ParsedGemini::Quote(quote.to_string()));
+            }
+            LineType::PreformatToggle(alt) => {
+                preformatted_alt = alt.map(str::to_string);
+                parsed_gemini.push(ParsedGemini::PreformattedStart(preformatted_alt.clone()));
+                flag_preformatted = true;
+            }
+            LineType::Text(text) => parsed_gemini.push(ParsedGemini::Text(text.to_string())),
+        }
+    }
+    // end of document, close any block still open
+    if flag_list {
+        parsed_gemini.push(ParsedGemini::ListEnd);
+    }
+    if flag_quote {
+        parsed_gemini.push(ParsedGemini::QuoteEnd);
+    }
+    if flag_preformatted {
+        parsed_gemini.push(ParsedGemini::PreformattedEnd(preformatted_alt));
+    }
+    (parsed_gemini, title)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_document() {
+        let document = parse(
+            "# title\nsome text\n=> gemini://fqdn/path a link\n* one\n* two\n> quote\n> again\n```\npre\n```\n## heading2\n",
+        );
+        assert_eq!(document.title, Some("title".to_string()));
+        assert_eq!(
+            document.blocks,
+            vec![
+                Block::Heading {
+                    level: 1,
+                    text: "title".to_string()
+                },
+                Block::Text("some text".to_string()),
+                Block::Link(Link {
+                    url: "gemini://fqdn/path".to_string(),
+                    description: Some("a link".to_string())
+                }),
+                Block::List(vec!["one".to_string(), "two".to_string()]),
+                Block::Quote(vec!["quote".to_string(), "again".to_string()]),
+                Block::Preformatted(Preformatted {
+                    alt: None,
+                    lines: vec!["pre".to_string()]
+                }),
+                Block::Heading {
+                    level: 2,
+                    text: "heading2".to_string()
+                },
+            ]
+        );
+    }
+    #[test]
+    fn test_split_link() {
+        assert_eq!(split_link("url"), ("url", None));
+        assert_eq!(split_link("   url  desc "), ("url", Some("desc")));
+        assert_eq!(split_link("url "), ("url", None));
+    }
+    #[test]
+    fn test_document_links() {
+        let document = parse("=> a\ntext\n=> b desc\n");
+        let urls: Vec<&str> = document.links().map(|link| link.url.as_str()).collect();
+        assert_eq!(urls, vec!["a", "b"]);
+    }
+
+    // conformance with https://geminiprotocol.net/docs/gemtext-specification.gmi
+
+    fn text(text: &str) -> Block {
+        Block::Text(text.to_string())
+    }
+    fn link(url: &str, description: Option<&str>) -> Block {
+        Block::Link(Link {
+            url: url.to_string(),
+            description: description.map(str::to_string),
+        })
+    }
+    fn heading(level: u8, text: &str) -> Block {
+        Block::Heading {
+            level,
+            text: text.to_string(),
+        }
+    }
+    fn strings(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn test_spec_text_lines() {
+        // anything not matching another line type is text, including empty lines
+        let document = parse("Some text\n\n  indented text\n");
+        assert_eq!(
+            document.blocks,
+            vec![text("Some text"), text(""), text("  indented text")]
+        );
+    }
+    #[test]
+    fn test_spec_link_lines() {
+        let document = parse(concat!(
+            "=> gemini://example.org/\n",
+            "=>gemini://example.org/ without space\n",
+            "=>   gemini://example.org/   many spaces  \n",
+            "=>\tgemini://example.org/\ttabs\n",
+            "=> /relative a description with   spaces\n",
+        ));
+        assert_eq!(
+            document.blocks,
+            vec![
+                link("gemini://example.org/", None),
+                link("gemini://example.org/", Some("without space")),
+                link("gemini://example.org/", Some("many spaces")),
+                link("gemini://example.org/", Some("tabs")),
+                link("/relative", Some("a description with   spaces")),
+            ]
+        );
+    }
+    #[test]
+    fn test_spec_link_lines_without_url() {
+        let document = parse("=>\n=>   \n = > not a link\n");
+        assert_eq!(
+            document.blocks,
+            vec![text("=>"), text("=>   "), text(" = > not a link")]
+        );
+    }
+    #[test]
+    fn test_spec_heading_lines() {
+        let document = parse("# Title\n#Title\n## Sub\n##Sub\n### Subsub\n###Subsub\n#### Deep\n");
+        assert_eq!(
+            document.blocks,
+            vec![
+                heading(1, "Title"),
+                heading(1, "Title"),
+                heading(2, "Sub"),
+                heading(2, "Sub"),
+                heading(3, "Subsub"),
+                heading(3, "Subsub"),
+                heading(3, "# Deep"),
+            ]
+        );
+        // heading prefix must be at the start of the line
+        assert_eq!(parse(" # not a title").blocks, vec![text(" # not a title")]);
+    }
+    #[test]
+    fn test_spec_list_lines() {
+        let document = parse("* one\n*  two\n*three\n * four\n");
+        assert_eq!(
+            document.blocks,
+            vec![
+                Block::List(strings(&["one", " two"])),
+                text("*three"),
+                text(" * four"),
+            ]
+        );
+    }
+    #[test]
+    fn test_spec_quote_lines() {
+        let document = parse("> a quote\n>another\n>\n");
+        assert_eq!(
+            document.blocks,
+            vec![Block::Quote(strings(&["a quote", "another", ""]))]
+        );
+    }
+    #[test]
+    fn test_spec_preformat_toggle_lines() {
+        let document = parse(concat!(
+            "```bash\n",
+            "  # indented, not a heading\n",
+            "=> not a link\n",
+            "```\n",
+            "``` \n",
+            "* not a list\n",
+            "``` closing alt text is ignored\n",
+            "after\n",
+        ));
+        assert_eq!(
+            document.blocks,
+            vec![
+                Block::Preformatted(Preformatted {
+                    alt: Some("bash".to_string()),
+                    lines: strings(&["  # indented, not a heading", "=> not a link"]),
+                }),
+                Block::Preformatted(Preformatted {
+                    alt: None,
+                    lines: strings(&["* not a list"]),
+                }),
+                text("after"),
+            ]
+        );
+    }
+    #[test]
+    fn test_spec_crlf_line_endings() {
+        let document = parse("# Title\r\n=> url desc\r\n");
+        assert_eq!(
+            document.blocks,
+            vec![heading(1, "Title"), link("url", Some("desc"))]
+        );
+    }
+    #[test]
+    fn test_consecutive_quotes_are_grouped() {
+        let document = parse("> one\n> two\ntext\n> three\n");
+        assert_eq!(
+            document.blocks,
+            vec![
+                Block::Quote(strings(&["one", "two"])),
+                text("text"),
+                Block::Quote(strings(&["three"])),
+            ]
+        );
+    }
+}
@@ -0,0 +1,478 @@
+//! Server side syntax highlighting for preformatted blocks
+//!
+//! A tiny tokenizer, far from a real parser, but good enough for technical notes :
+//! comments, strings, numbers and keywords are wrapped in `<span>` tags with a
+//! `hl-xxx` class, styled by `highlight.css`. No javascript needed.
+
+use crate::escaped_text;
+
+/// Syntax elements of a language
+struct Language {
+    keywords: &'static [&'static str],
+    line_comment: Option<&'static str>,
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+}
+
+const RUST: Language = Language {
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+        "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+        "true", "type", "unsafe", "use", "where", "while",
+    ],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"'],
+};
+
+const C: Language = Language {
+    keywords: &[
+        "auto",
+        "bool",
+        "break",
+        "case",
+        "char",
+        "class",
+        "const",
+        "continue",
+        "default",
+        "delete",
+        "do",
+        "double",
+        "else",
+        "enum",
+        "extern",
+        "false",
+        "float",
+        "for",
+        "goto",
+        "if",
+        "include",
+        "define",
+        "inline",
+        "int",
+        "long",
+        "namespace",
+        "new",
+        "nullptr",
+        "private",
+        "protected",
+        "public",
+        "return",
+        "short",
+        "signed",
+        "sizeof",
+        "static",
+        "struct",
+        "switch",
+        "template",
+        "this",
+        "true",
+        "typedef",
+        "union",
+        "unsigned",
+        "using",
+        "virtual",
+        "void",
+        "volatile",
+        "while",
+    ],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+};
+
+const GO: Language = Language {
+    keywords: &[
+        "break",
+        "case",
+        "chan",
+        "const",
+        "continue",
+        "default",
+        "defer",
+        "else",
+        "false",
+        "fallthrough",
+        "for",
+        "func",
+        "go",
+        "goto",
+        "if",
+        "import",
+        "interface",
+        "map",
+        "nil",
+        "package",
+        "range",
+        "return",
+        "select",
+        "struct",
+        "switch",
+        "true",
+        "type",
+        "var",
+    ],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\'', '`'],
+};
+
+const JAVASCRIPT: Language = Language {
+    keywords: &[
+        "async",
+        "await",
+        "break",
+        "case",
+        "catch",
+        "class",
+        "const",
+        "continue",
+        "default",
+        "delete",
+        "do",
+        "else",
+        "export",
+        "extends",
+        "false",
+        "finally",
+        "for",
+        "function",
+        "if",
+        "import",
+        "in",
+        "instanceof",
+        "interface",
+        "let",
+        "new",
+        "null",
+        "return",
+        "static",
+        "super",
+        "switch",
+        "this",
+        "throw",
+        "true",
+        "try",
+        "type",
+        "typeof",
+        "undefined",
+        "var",
+        "void",
+        "while",
+        "yield",
+    ],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\'', '`'],
+};
+
+const JAVA: Language = Language {
+    keywords: &[
+        "abstract",
+        "boolean",
+        "break",
+        "case",
+        "catch",
+        "char",
+        "class",
+        "continue",
+        "default",
+        "do",
+        "double",
+        "else",
+        "enum",
+        "extends",
+        "false",
+        "final",
+        "finally",
+        "float",
+        "for",
+        "if",
+        "implements",
+        "import",
+        "instanceof",
+        "int",
+        "interface",
+        "long",
+        "new",
+        "null",
+        "package",
+        "private",
+        "protected",
+        "public",
+        "return",
+        "static",
+        "super",
+        "switch",
+        "this",
+        "throw",
+        "throws",
+        "true",
+        "try",
+        "void",
+        "while",
+    ],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+};
+
+const PYTHON: Language = Language {
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+        "elif", "else", "except", "False", "finally", "for", "from", "global", "if", "import",
+        "in", "is", "lambda", "None", "nonlocal", "not", "or", "pass", "raise", "return", "True",
+        "try", "while", "with", "yield",
+    ],
+    line_comment: Some("#"),
+    block_comment: None,
+    quotes: &['"', '\''],
+};
+
+const SHELL: Language = Language {
+    keywords: &[
+        "case", "do", "done", "echo", "elif", "else", "esac", "exit", "export", "fi", "for",
+        "function", "if", "in", "local", "read", "return", "set", "then", "unset", "until",
+        "while",
+    ],
+    line_comment: Some("#"),
+    block_comment: None,
+    quotes: &['"', '\''],
+};
+
+const LUA: Language = Language {
+    keywords: &[
+        "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "if", "in",
+        "local", "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+    ],
+    line_comment: Some("--"),
+    block_comment: None,
+    quotes: &['"', '\''],
+};
+
+const SQL: Language = Language {
+    keywords: &[
+        "and", "as", "by", "create", "delete", "from", "group", "insert", "into", "join", "left",
+        "not", "null", "on", "or", "order", "select", "set", "table", "update", "values", "where",
+        "AND", "AS", "BY", "CREATE", "DELETE", "FROM", "GROUP", "INSERT", "INTO", "JOIN", "LEFT",
+        "NOT", "NULL", "ON", "OR", "ORDER", "SELECT", "SET", "TABLE", "UPDATE", "VALUES", "WHERE",
+    ],
+    line_comment: Some("--"),
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+};
+
+const CONFIG: Language = Language {
+    keywords: &["true", "false", "null", "yes", "no"],
+    line_comment: Some("#"),
+    block_comment: None,
+    quotes: &['"', '\''],
+};
+
+const JSON: Language = Language {
+    keywords: &["true", "false", "null"],
+    line_comment: None,
+    block_comment: None,
+    quotes: &['"'],
+};
+
+/// Find a language from its name, as found in the preformatted alt text
+fn language(name: &str) -> Option<&'static Language> {
+    match name {
+        "rust" | "rs" => Some(&RUST),
+        "c" | "h" | "cpp" | "c++" | "cxx" | "hpp" => Some(&C),
+        "go" | "golang" => Some(&GO),
+        "javascript" | "js" | "typescript" | "ts" => Some(&JAVASCRIPT),
+        "java" | "kotlin" => Some(&JAVA),
+        "python" | "py" | "python3" => Some(&PYTHON),
+        "bash" | "sh" | "shell" | "zsh" | "console" => Some(&SHELL),
+        "lua" => Some(&LUA),
+        "sql" => Some(&SQL),
+        "toml" | "ini" | "yaml" | "yml" => Some(&CONFIG),
+        "json" => Some(&JSON),
+        _ => None,
+    }
+}
+
+/// Highlight a preformatted block line by line
+/// a state is kept between lines for multi-lines comments
+pub(crate) struct Highlighter {
+    language: &'static Language,
+    in_block_comment: bool,
+}
+
+impl Highlighter {
+    /// Create a highlighter, only if the language is known
+    pub(crate) fn new(language_name: &str) -> Option<Highlighter> {
+        language(language_name).map(|language| Highlighter {
+            language,
+            in_block_comment: false,
+        })
+    }
+
+    /// Return the escaped line, with syntax elements wrapped in `<span>` tags
+    pub(crate) fn highlight_line(&mut self, line: &str) -> String {
+        let mut html = String::new();
+        let mut rest = line;
+        while !rest.is_empty() {
+            // continue a block comment from previous lines
+            if self.in_block_comment
+                && let Some((_, end)) = self.language.block_comment
+            {
+                let comment_length = match rest.find(end) {
+                    Some(position) => {
+                        self.in_block_comment = false;
+                        position + end.len()
+                    }
+                    None => rest.len(),
+                };
+                html.push_str(&span("comment", &rest[..comment_length]));
+                rest = &rest[comment_length..];
+                continue;
+            }
+            let token_length = if self.is_line_comment(line, rest) {
+                html.push_str(&span("comment", rest));
+                rest.len()
+            } else if let Some((start, end)) = self.language.block_comment
+                && rest.starts_with(start)
+            {
+                let length = match rest[start.len()..].find(end) {
+                    Some(position) => start.len() + position + end.len(),
+                    None => {
+                        self.in_block_comment = true;
+                        rest.len()
+                    }
+                };
+                html.push_str(&span("comment", &rest[..length]));
+                length
+            } else {
+                let first_char = rest.chars().next().unwrap_or_default();
+                if self.language.quotes.contains(&first_char) {
+                    let length = string_length(rest, first_char);
+                    html.push_str(&span("string", &rest[..length]));
+                    length
+                } else if first_char.is_ascii_digit() && !ends_with_word(line, rest) {
+                    let length = number_length(rest);
+                    html.push_str(&span("number", &rest[..length]));
+                    length
+                } else if first_char.is_alphabetic() || first_char == '_' {
+                    let length = word_length(rest);
+                    let word = &rest[..length];
+                    if self.language.keywords.contains(&word) {
+                        html.push_str(&span("keyword", word));
+                    } else {
+                        html.push_str(word);
+                    }
+                    length
+                } else {
+                    let length = first_char.len_utf8();
+                    html.push_str(&escaped_text(&rest[..length]));
+                    length
+                }
+            };
+            rest = &rest[token_length..];
+        }
+        html
+    }
+
+    /// A line comment starts here ?
+    /// `#` comments must start a word, `$#` or `a#b` are not comments in shell
+    fn is_line_comment(&self, line: &str, rest: &str) -> bool {
+        match self.language.line_comment {
+            Some("#") => {
+                rest.starts_with('#')
+                    && line[..line.len() - rest.len()]
+                        .chars()
+                        .last()
+                        .is_none_or(char::is_whitespace)
+            }
+            Some(prefix) => rest.starts_with(prefix),
+            None => false,
+        }
+    }
+}
+
+/// Wrap escaped text in a classed `<span>`
+fn span(class: &str, text: &str) -> String {
+    format!("<span class=\"hl-{class}\">{}</span>", escaped_text(text))
+}
+
+/// Length of a string literal starting with `quote`, with backslash escapes
+/// an unterminated string goes until the end of the line
+fn string_length(text: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (position, c) in text.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return position + c.len_utf8();
+        }
+    }
+    text.len()
+}
+
+/// Length of an identifier
+fn word_length(text: &str) -> usize {
+    text.find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(text.len())
+}
+
+/// Length of a number, with its decimal part and suffix (`1.5`, `0xff`, `42u8`)
+fn number_length(text: &str) -> usize {
+    text.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+        .unwrap_or(text.len())
+}
+
+/// Is the char before `rest` part of a word ? (`x1` is not a number)
+fn ends_with_word(line: &str, rest: &str) -> bool {
+    line[..line.len() - rest.len()]
+        .chars()
+        .last()
+        .is_some_and(|c| c.is_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_language() {
+        assert!(Highlighter::new("a cat").is_none());
+    }
+    #[test]
+    fn test_highlight_rust() {
+        let mut highlighter = Highlighter::new("rust").unwrap();
+        assert_eq!(
+            highlighter.highlight_line("let x = \"<a>\"; // 42"),
+            "<span class=\"hl-keyword\">let</span> x = <span class=\"hl-string\">\"&lt;a&gt;\"</span>; <span class=\"hl-comment\">// 42</span>"
+        );
+        assert_eq!(
+            highlighter.highlight_line("x1 + 1.5"),
+            "x1 + <span class=\"hl-number\">1.5</span>"
+        );
+    }
+    #[test]
+    fn test_highlight_block_comment_on_many_lines() {
+        let mut highlighter = Highlighter::new("c").unwrap();
+        assert_eq!(
+            highlighter.highlight_line("int a; /* start"),
+            "<span class=\"hl-keyword\">int</span> a; <span class=\"hl-comment\">/* start</span>"
+        );
+        assert_eq!(
+            highlighter.highlight_line("end */ a /* b */ self.c"),
+            "<span class=\"hl-comment\">end */</span> a <span class=\"hl-comment\">/* b */</span> self.c"
+        );
+    }
+    #[test]
+    fn test_highlight_shell_comments() {
+        let mut highlighter = Highlighter::new("bash").unwrap();
+        assert_eq!(
+            highlighter.highlight_line("echo $# 'it\\'s' # comment"),
+            "<span class=\"hl-keyword\">echo</span> $# <span class=\"hl-string\">'it\\'s'</span> <span class=\"hl-comment\"># comment</span>"
+        );
+    }
+}
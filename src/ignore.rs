@@ -0,0 +1,368 @@
+//! Ignore rules : which files of the source tree are built
+//!
+//! A `.gemini2htmlignore` at the root of the capsule uses the gitignore syntax,
+//! its patterns are relative to the root. The `exclude` globs of the configuration
+//! are added after it, and `include` globs restrict the build to some files.
+//! Drafts, named like `post.draft.gmi`, are never built unless asked.
+
+use crate::site::SiteOptions;
+use crate::{Result, read_from_file};
+use std::path::{Component, Path};
+
+/// Name of the ignore file, at the root of the capsule
+pub const IGNORE_FILE_NAME: &str = ".gemini2htmlignore";
+
+/// Suffix of the file stem of drafts
+const DRAFT_SUFFIX: &str = ".draft";
+
+/// A gitignore pattern
+#[derive(Debug, Clone, PartialEq)]
+struct Pattern {
+    glob: Vec<char>,
+    /// `!pattern`, files matching it are built again
+    negated: bool,
+    /// `pattern/`, only matches directories
+    directory_only: bool,
+    /// Patterns with a `/` match the whole path, the others the file name
+    anchored: bool,
+}
+
+impl Pattern {
+    /// Parse a line of an ignore file, `None` for blank lines and comments
+    /// a leading `\!` or `\#` is a literal `!` or `#`
+    fn parse(line: &str) -> Option<Pattern> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(line) => (true, line),
+            None => (
+                false,
+                line.strip_prefix('\\')
+                    .filter(|line| line.starts_with(['!', '#']))
+                    .unwrap_or(line),
+            ),
+        };
+        let (directory_only, line) = match line.strip_suffix('/') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let glob = line.strip_prefix('/').unwrap_or(line);
+        (!glob.is_empty()).then(|| Pattern {
+            glob: glob.chars().collect(),
+            negated,
+            directory_only,
+            anchored,
+        })
+    }
+
+    /// True if the pattern matches a path, relative to the root of the capsule
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.directory_only && !is_dir {
+            return false;
+        }
+        let components: Vec<_> = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy()),
+                _ => None,
+            })
+            .collect();
+        let text: Vec<char> = match self.anchored {
+            true => components.join("/").chars().collect(),
+            false => components
+                .last()
+                .map_or(Vec::new(), |name| name.chars().collect()),
+        };
+        glob_match(&self.glob, &text)
+    }
+}
+
+/// Match a glob : `*` and `?` don't match `/`, a `**` path component matches any number
+/// of directories, `[a-z]` and `[!a-z]` are character classes and `\` escapes the next character
+/// a `**` which is not a whole component is a `*`, and a trailing `/**` matches what is inside
+///
+/// Wildcards are matched from the left, going back only to the last one : the time is
+/// bounded by the product of the lengths, whatever the number of wildcards
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    let mut components: Vec<_> = pattern.split(|c| *c == '/').collect();
+    // `a/**` needs something inside `a`
+    if components.len() > 1 && components.last() == Some(&&['*', '*'][..]) {
+        components.insert(components.len() - 1, &['*']);
+    }
+    let names: Vec<_> = text.split(|c| *c == '/').collect();
+    let (mut p, mut n) = (0, 0);
+    // after the last `**` : its next component, and the first name it did not match
+    let mut any_directories = None;
+    while n < names.len() {
+        match components.get(p) {
+            Some(['*', '*']) => {
+                any_directories = Some((p + 1, n));
+                p += 1;
+                continue;
+            }
+            Some(component) if name_match(component, names[n]) => {
+                p += 1;
+                n += 1;
+                continue;
+            }
+            _ => {}
+        }
+        // the last `**` matches one more name
+        let Some((next, matched)) = any_directories else {
+            return false;
+        };
+        any_directories = Some((next, matched + 1));
+        (p, n) = (next, matched + 1);
+    }
+    components[p..]
+        .iter()
+        .all(|component| *component == ['*', '*'])
+}
+
+/// Match a glob against a name, without `/`
+fn name_match(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // after the last `*` : its next character, and the first one it did not match
+    let mut any_characters = None;
+    while n < name.len() {
+        if pattern.get(p) == Some(&'*') {
+            any_characters = Some((p + 1, n));
+            p += 1;
+            continue;
+        }
+        if let Some(length) = character_match(&pattern[p..], name[n]) {
+            p += length;
+            n += 1;
+            continue;
+        }
+        // the last `*` matches one more character
+        let Some((next, matched)) = any_characters else {
+            return false;
+        };
+        any_characters = Some((next, matched + 1));
+        (p, n) = (next, matched + 1);
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Match a character against the start of a glob, without `*`
+/// return the length of the matching part of the glob
+fn character_match(pattern: &[char], c: char) -> Option<usize> {
+    let (matches, rest) = match pattern {
+        [] => return None,
+        ['?', rest @ ..] => (c != '/', rest),
+        ['[', class @ ..] if let Some(class) = character_class(class, Some(&c)) => class,
+        ['\\', escaped, rest @ ..] | [escaped, rest @ ..] => (*escaped == c, rest),
+    };
+    matches.then_some(pattern.len() - rest.len())
+}
+
+/// Match a character against a class, after its `[`
+/// return the result and the rest of the pattern, `None` if the class is not closed
+fn character_class<'a>(class: &'a [char], c: Option<&char>) -> Option<(bool, &'a [char])> {
+    let (negated, class) = match class {
+        ['!' | '^', class @ ..] => (true, class),
+        _ => (false, class),
+    };
+    // a `]` first is part of the class
+    let end = class.iter().skip(1).position(|c| *c == ']')? + 1;
+    let (members, rest) = (&class[..end], &class[end + 1..]);
+    let Some(c) = c.filter(|c| **c != '/') else {
+        return Some((false, rest));
+    };
+    let mut found = false;
+    let mut i = 0;
+    while i < members.len() {
+        if members.get(i + 1) == Some(&'-') && i + 2 < members.len() {
+            found |= (members[i]..=members[i + 2]).contains(c);
+            i += 3;
+        } else {
+            found |= members[i] == *c;
+            i += 1;
+        }
+    }
+    Some((found != negated, rest))
+}
+
+/// True if a file is a draft, like `post.draft.gmi`
+fn is_draft(path: &Path) -> bool {
+    path.file_stem()
+        .is_some_and(|stem| stem.to_string_lossy().ends_with(DRAFT_SUFFIX))
+}
+
+/// Which files of the source tree are built
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct SourceFilter {
+    /// Rules of the ignore file then `exclude` globs, the last matching one wins
+    exclude: Vec<Pattern>,
+    include: Vec<Pattern>,
+    drafts: bool,
+}
+
+impl SourceFilter {
+    /// Rules of a capsule, from its ignore file and the options
+    pub(crate) fn load(
+        source_tree_directory: &Path,
+        site_options: &SiteOptions,
+    ) -> Result<SourceFilter> {
+        let ignore_file = source_tree_directory.join(IGNORE_FILE_NAME);
+        let content = match ignore_file.is_file() {
+            true => read_from_file(&ignore_file)?,
+            false => String::new(),
+        };
+        Ok(SourceFilter::new(&content, site_options))
+    }
+
+    /// Rules from the content of an ignore file and the options
+    fn new(ignore_file: &str, site_options: &SiteOptions) -> SourceFilter {
+        SourceFilter {
+            exclude: ignore_file
+                .lines()
+                .chain(site_options.exclude.iter().map(String::as_str))
+                .filter_map(Pattern::parse)
+                .collect(),
+            include: site_options
+                .include
+                .iter()
+                .filter_map(|glob| Pattern::parse(glob))
+                .collect(),
+            drafts: site_options.drafts,
+        }
+    }
+
+    /// True if a file or a directory is not built, its path is relative to the root of the capsule
+    /// `include` globs only apply to files, directories are walked to find them
+    pub(crate) fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        if !is_dir && !self.drafts && is_draft(path) {
+            return true;
+        }
+        let excluded = self
+            .exclude
+            .iter()
+            .rev()
+            .find(|pattern| pattern.matches(path, is_dir))
+            .is_some_and(|pattern| !pattern.negated);
+        // a file is included by its own path, or one of its directories
+        let directories = path
+            .ancestors()
+            .skip(1)
+            .filter(|directory| !directory.as_os_str().is_empty());
+        let included = is_dir
+            || self.include.is_empty()
+            || self.include.iter().any(|pattern| {
+                pattern.matches(path, false)
+                    || directories
+                        .clone()
+                        .any(|directory| pattern.matches(directory, true))
+            });
+        excluded || !included
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(pattern: &str, text: &str) -> bool {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let text: Vec<char> = text.chars().collect();
+        glob_match(&pattern, &text)
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob("*.swp", ".index.gmi.swp"));
+        assert!(!glob("*.gmi", "posts/index.gmi"));
+        assert!(glob("posts/*.gmi", "posts/index.gmi"));
+        assert!(glob("**/notes.gmi", "notes.gmi"));
+        assert!(glob("**/notes.gmi", "a/b/notes.gmi"));
+        assert!(glob("private/**", "private/a/b.png"));
+        assert!(!glob("private/**", "private"));
+        assert!(glob("a/**/b", "a/b"));
+        assert!(glob("a/**/b", "a/x/y/b"));
+        assert!(glob("file?.txt", "file1.txt"));
+        assert!(!glob("a?b", "a/b"));
+        assert!(glob("[a-c]at.gmi", "bat.gmi"));
+        assert!(!glob("[!a-c]at.gmi", "bat.gmi"));
+        assert!(glob("[!a-c]at.gmi", "rat.gmi"));
+        assert!(glob("[]]", "]"));
+        assert!(glob("[unclosed", "[unclosed"));
+        assert!(glob("\\#notes", "#notes"));
+        assert!(glob("*~", "index.gmi~"));
+        // `**` is only special as a whole component
+        assert!(glob("**.gmi", "index.gmi"));
+        assert!(!glob("posts/**.gmi", "posts/2026/index.gmi"));
+        assert!(!glob("a**b", "a/b"));
+        assert!(glob("a/**", "a/b/c"));
+        assert!(glob("**", "a/b"));
+        assert!(glob("a/**/b/**/c", "a/x/b/b/y/c"));
+        assert!(!glob("a/**/b", "a/x/c"));
+        // many wildcards don't take exponential time
+        let text = "a".repeat(100);
+        assert!(!glob(&format!("{}b", "*a".repeat(30)), &text));
+        let text = ["a"; 100].join("/");
+        assert!(!glob(&format!("{}b", "**/a/".repeat(30)), &text));
+    }
+    #[test]
+    fn test_pattern_escapes() {
+        let pattern = |line: &str| {
+            Pattern::parse(line)
+                .map(|pattern| (pattern.glob.iter().collect::<String>(), pattern.negated))
+        };
+        assert_eq!(pattern("#comment"), None);
+        assert_eq!(pattern("\\#notes"), Some(("#notes".to_string(), false)));
+        assert_eq!(pattern("!important"), Some(("important".to_string(), true)));
+        assert_eq!(
+            pattern("\\!important"),
+            Some(("!important".to_string(), false))
+        );
+        assert_eq!(
+            pattern("!\\!important"),
+            Some(("\\!important".to_string(), true))
+        );
+        let filter = SourceFilter::new("\\!important\n\\#notes\n", &SiteOptions::default());
+        assert!(filter.is_excluded(Path::new("!important"), false));
+        assert!(filter.is_excluded(Path::new("#notes"), false));
+        assert!(!filter.is_excluded(Path::new("important"), false));
+    }
+    #[test]
+    fn test_source_filter() {
+        let ignore_file =
+            "# editor files\n*.swp\n*~\n\n.git/\n/notes/\nprivate/**\n!private/shared.png\n";
+        let site_options = SiteOptions {
+            exclude: vec!["*.log".to_string()],
+            ..SiteOptions::default()
+        };
+        let filter = SourceFilter::new(ignore_file, &site_options);
+        let excluded = |path: &str, is_dir: bool| filter.is_excluded(Path::new(path), is_dir);
+        assert!(excluded(".index.gmi.swp", false));
+        assert!(excluded("posts/index.gmi~", false));
+        assert!(excluded(".git", true));
+        assert!(excluded("sub/.git", true));
+        // only directories match `.git/`
+        assert!(!excluded(".git", false));
+        // `/notes/` is anchored at the root
+        assert!(excluded("notes", true));
+        assert!(!excluded("posts/notes", true));
+        assert!(excluded("private/key.png", false));
+        assert!(!excluded("private/shared.png", false));
+        assert!(excluded("build.log", false));
+        assert!(excluded("posts/my-post.draft.gmi", false));
+        assert!(!excluded("index.gmi", false));
+        // drafts can be built
+        let site_options = SiteOptions {
+            drafts: true,
+            include: vec!["*.gmi".to_string(), "images/".to_string()],
+            ..SiteOptions::default()
+        };
+        let filter = SourceFilter::new("", &site_options);
+        assert!(!filter.is_excluded(Path::new("posts/my-post.draft.gmi"), false));
+        // only included files are built, every directory is walked
+        assert!(filter.is_excluded(Path::new("notes.txt"), false));
+        assert!(!filter.is_excluded(Path::new("images/photos/cat.png"), false));
+        assert!(!filter.is_excluded(Path::new("notes"), true));
+    }
+}
@@ -1,79 +1,156 @@
-use log::{debug, error, info};
-use std::fmt;
-use std::fs::File;
-use std::io::{Read, Write};
+//! Convert gemtext files to html
+//!
+//! The parser and its document model live in the [`gemtext`] module,
+//! use [`parse`] to get a [`Document`] out of some gemtext.
+
+use log::{debug, info};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
 use std::path::Path;
 
-/// Error handling, I should rework this awfull part...
-pub type Result<T> = std::result::Result<T, Gemini2HtmlError>;
-#[derive(Debug, Clone, PartialEq)]
-pub struct Gemini2HtmlError;
-impl fmt::Display for Gemini2HtmlError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "😢 Sorry, gemini2html encountered an error.")
-    }
-}
+pub mod config;
+mod error;
+pub use error::{Gemini2HtmlError, Operation, Result};
+pub mod feed;
+pub mod gemlog;
+pub mod gemtext;
+#[cfg(feature = "highlight")]
+mod highlight;
+mod ignore;
+pub use ignore::IGNORE_FILE_NAME;
+mod manifest;
+pub use manifest::MANIFEST_FILE_NAME;
+pub mod serve;
+pub mod site;
+pub mod template;
+#[cfg(test)]
+mod test_tree;
+pub mod theme;
+#[cfg(target_os = "linux")]
+pub mod watch;
+use feed::FeedLinks;
+pub use gemtext::{Block, Document, Link, Preformatted, parse};
+use gemtext::{ParsedGemini, parse_gemini, split_link};
+use template::{Context, Source, Template};
+pub use theme::Theme;
 
-/// Gemini syntax elements
-/// see https://geminiprotocol.net/docs/gemtext-specification.gmi for details
-enum ParsedGemini {
-    Link(String),
-    Heading1(String),
-    Heading2(String),
-    Heading3(String),
-    ListStart,
-    ListEnd,
-    ListElement(String),
-    Quote(String),
-    Text(String),
-    PreformattedStart,
-    PreformattedEnd,
-    PreformattedText(String),
-}
 impl ParsedGemini {
     /// format gemini elements to html
-    fn to_html(&self) -> String {
+    fn to_html(&self, options: &Options) -> String {
         match self {
-            ParsedGemini::Link(link) => html_link(link),
-            ParsedGemini::Heading1(heading) => format!("<h1>{}</h1>", heading),
-            ParsedGemini::Heading2(heading) => format!("<h2>{}</h2>", heading),
-            ParsedGemini::Heading3(heading) => format!("<h3>{}</h3>", heading),
+            ParsedGemini::Link(link) => html_link(link, options),
+            ParsedGemini::Heading1(heading) => format!("<h1>{}</h1>", escaped_text(heading)),
+            ParsedGemini::Heading2(heading) => format!("<h2>{}</h2>", escaped_text(heading)),
+            ParsedGemini::Heading3(heading) => format!("<h3>{}</h3>", escaped_text(heading)),
             ParsedGemini::ListStart => "<ul>".to_string(),
             ParsedGemini::ListEnd => "</ul>".to_string(),
-            ParsedGemini::ListElement(list) => format!("<li>{}</li>", list),
-            // TODO quote here, no `<br />`
-            ParsedGemini::Quote(quote) => format!("TODO QUOTE: {}<br />", quote),
-            ParsedGemini::PreformattedStart => "<pre>".to_string(),
-            ParsedGemini::PreformattedEnd => "</pre>".to_string(),
-            ParsedGemini::PreformattedText(text) => escaped_preformat_text(text),
-            ParsedGemini::Text(text) => format!("<p>{}</p>", text),
+            ParsedGemini::ListElement(list) => format!("<li>{}</li>", escaped_text(list)),
+            ParsedGemini::QuoteStart => "<blockquote>".to_string(),
+            ParsedGemini::QuoteEnd => "</blockquote>".to_string(),
+            ParsedGemini::Quote(quote) => format!("<p>{}</p>", escaped_text(quote)),
+            ParsedGemini::PreformattedStart(alt) => html_preformatted_start(alt.as_deref()),
+            ParsedGemini::PreformattedEnd(alt) => match alt {
+                Some(_) => "</code></pre>\n</figure>".to_string(),
+                None => "</pre>".to_string(),
+            },
+            ParsedGemini::PreformattedText(text) => escaped_text(text),
+            ParsedGemini::Text(text) => format!("<p>{}</p>", escaped_text(text)),
         }
     }
 }
 
 /// Return html headers, the title of the page should be known
-fn html_headers(title: Option<&str>) -> String {
-    let title = title.unwrap_or("some title");
-    let css = html_style();
+fn html_headers(title: Option<&str>, source: &Source, options: &Options) -> String {
+    // the site name follows the title of the page, and replaces a missing one
+    let title = match (title, options.site_name.as_deref()) {
+        (Some(title), Some(site_name)) if title != site_name => format!("{title} - {site_name}"),
+        (Some(title), _) | (None, Some(title)) => title.to_string(),
+        (None, None) => "some title".to_string(),
+    };
+    let lang = match &options.language {
+        Some(language) => format!(" lang=\"{}\"", escaped_attribute(language)),
+        None => String::new(),
+    };
+    let author = match &options.author {
+        Some(author) => format!(
+            "<meta name=\"author\" content=\"{}\" />\n",
+            escaped_attribute(author)
+        ),
+        None => String::new(),
+    };
+    // let feed readers find the feeds
+    let mut feeds = String::new();
+    if let Some(base_url) = &options.base_url {
+        let alternates = [
+            (
+                options.feed_links.atom,
+                "application/atom+xml",
+                feed::ATOM_FILE_NAME,
+            ),
+            (
+                options.feed_links.rss,
+                "application/rss+xml",
+                feed::RSS_FILE_NAME,
+            ),
+        ];
+        for (_, mime_type, file_name) in alternates.iter().filter(|(enabled, ..)| *enabled) {
+            feeds.push_str(&format!(
+                "<link rel=\"alternate\" type=\"{mime_type}\" href=\"{}\" />\n",
+                escaped_attribute(&feed::absolute_url(base_url, Path::new(file_name)))
+            ));
+        }
+    }
+    let css = html_style(source, options);
     format!(
-        "<!doctype html>\n<html>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\" />\n{}\n<title>{}</title>\n<body>\n",
-        css, title
+        "<!doctype html>\n<html{}>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\" />\n{}{}{}\n<title>{}</title>\n<body>\n",
+        lang,
+        author,
+        feeds,
+        css,
+        escaped_text(&title)
     )
 }
 
-/// Read css file and put it in `style` tags
-fn html_style() -> String {
-    // let css_path = Path::new("style.css");
-    // let css = fs::read_to_string(css_path).expect("unable to read file in ./output_tests");
-    let css = include_bytes!("style.css");
-    let css = match std::str::from_utf8(css) {
-        Ok(css) => css.to_string(),
-        Err(e) => {
-            error!("unable to find css file {:?}", e);
-            String::from("")
+/// Put the css in `style` tags, or link the shared or external stylesheet
+pub(crate) fn html_style(source: &Source, options: &Options) -> String {
+    match &options.stylesheet {
+        Stylesheet::Inline => format!("<style>\n{}</style>", stylesheet(options)),
+        Stylesheet::Shared => {
+            // the stylesheet is at the root of the site
+            let depth = source
+                .path
+                .and_then(Path::parent)
+                .map_or(0, |parent| parent.components().count());
+            format!(
+                "<link rel=\"stylesheet\" href=\"{}{STYLESHEET_FILE_NAME}\" />",
+                "../".repeat(depth)
+            )
         }
+        Stylesheet::External(url) => {
+            format!("<link rel=\"stylesheet\" href=\"{}\" />", escaped_url(url))
+        }
+    }
+}
+
+/// The css of the pages : the theme or its replacement, followed by the extra css
+pub(crate) fn stylesheet(options: &Options) -> String {
+    let css = match &options.css {
+        Some(css) => css.clone(),
+        None => builtin_css(options.theme),
     };
-    format!("<style>\n{css}</style>")
+    match &options.extra_css {
+        Some(extra_css) => format!("{css}{extra_css}"),
+        None => css,
+    }
+}
+
+/// The css of the theme
+fn builtin_css(theme: Theme) -> String {
+    let css = theme.css().to_string();
+    // highlighting classes only exist with the feature
+    #[cfg(feature = "highlight")]
+    let css = format!("{css}{}", include_str!("highlight.css"));
+    css
 }
 
 /// Return html footer and closing tags, we can pass some infos here
@@ -82,198 +159,401 @@ fn html_footers(infos: &str) -> String {
     format!("<div class=footer>{infos}</div></body>\n</html>\n")
 }
 
-/// Read a line, an replace characters that must be escaped for preformatted html
-fn escaped_preformat_text(text: &str) -> String {
+/// Read a line, an replace characters that must be escaped in html text content
+/// see https://developer.mozilla.org/en-US/docs/Web/HTML/Reference/Elements/pre
+/// `&` -> `&amp;`
+/// `<` and `>` -> `&lt;` and `&gt;`
+pub(crate) fn escaped_text(text: &str) -> String {
     text.replace('&', "&amp;")
         .replace('<', "&lt;")
         .replace('>', "&gt;")
 }
 
+/// Read a line, an replace characters that must be escaped in a quoted html attribute value
+pub(crate) fn escaped_attribute(text: &str) -> String {
+    escaped_text(text)
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Escape an url for a `href` or `src` attribute
+/// urls with a scheme able to run code (`javascript:`...) are replaced by `#`
+fn escaped_url(url: &str) -> String {
+    // browsers ignore control chars and spaces in schemes, so do we
+    let scheme: String = url
+        .split_once(':')
+        .map(|(scheme, _)| scheme)
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_ascii_control() && !c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase();
+    match scheme.as_str() {
+        "javascript" | "vbscript" | "data" => "#".to_string(),
+        _ => escaped_attribute(url),
+    }
+}
+
+/// Opening tags of a preformatted block
+/// alt text is used as a caption and an accessible label,
+/// if it is a language name, it's also used as `language-xxx` class
+fn html_preformatted_start(alt: Option<&str>) -> String {
+    match alt {
+        Some(alt) => {
+            let class = match language_name(alt) {
+                Some(language) => format!(" class=\"language-{}\"", escaped_attribute(&language)),
+                None => String::new(),
+            };
+            format!(
+                "<figure>\n<figcaption>{}</figcaption>\n<pre aria-label=\"{}\"><code{class}>",
+                escaped_text(alt),
+                escaped_attribute(alt)
+            )
+        }
+        None => "<pre>".to_string(),
+    }
+}
+
+/// Names of programming and markup languages, with their usual aliases,
+/// the ones of the highlighter first, separated by spaces
+const LANGUAGES: &str = "\
+    rust rs c h cpp c++ cxx hpp go golang javascript js typescript ts java kotlin python py \
+    python3 bash sh shell zsh console lua sql toml ini yaml yml json asm awk c# csharp \
+    clojure cmake css d dart diff dockerfile elixir elm erlang f# fish fortran gemtext gmi \
+    graphql groovy haskell hs html jsx julia latex tex lisp makefile make markdown md nim \
+    nix objective-c objc ocaml pascal patch perl php powershell ps1 prolog r racket ruby rb \
+    scala scheme scss swift tcl tsx vim xml zig";
+
+/// Return the language name if the alt text is a known one : `bash`, `C++`, `objective-c`...
+fn language_name(alt: &str) -> Option<String> {
+    let language = alt.to_lowercase();
+    LANGUAGES
+        .split_whitespace()
+        .any(|name| name == language)
+        .then_some(language)
+}
+
 /// Format a gimini link [+ description] to a html link `<a>` tag,
 /// if the link seems to point an image, format a `<img>` tag
-fn html_link(link: &str) -> String {
-    // trim start before split or description will be lost
-    let link = link.trim_start();
+/// local gemini files, with `gemini_extension`, are linked to their html conversion
+fn html_link(link: &str, options: &Options) -> String {
     // if a description is present, use it in <a> tag
-    let (url, description) = match link.split_once(' ') {
-        Some(splitted_link) => splitted_link,
-        // if no description is provided, use the link as text
-        None => (link, link),
-    };
+    // if no description is provided, use the link as text
+    let (url, description) = split_link(link);
+    let description = description.unwrap_or(url);
     // `gemini://geminiprotocol.net/docs/gemtext-specification.gmi A link...`
-    // handle image
-    let image_html_options = r#"loading="lazy" height="200" sizes="auto, (max-width: 30em) 100vw, (max-width: 50em) 50vw, calc(33vw - 100px)""#;
-    // let image_html_options = r#"loading="lazy" width="200" height="200" sizes="auto, (max-width: 30em) 100vw, (max-width: 50em) 50vw, calc(33vw - 100px)""#;
-    let image_format = format!(
-        "<a href=\"{url}\"><img {image_html_options} src=\"{url}\" alt=\"{description}\" /></a><br />"
-    );
-    let standard_link_format = format!("<a href=\"{url}\">{description}</a><br />");
-    // try to match a known image extension
-    // and if gmi file, convert local files to html
-    match url.rsplit_once('.') {
-        Some((_reste, extension)) => match extension.to_lowercase().as_str() {
-            "jpg" => image_format,
-            "png" => image_format,
-            "gif" => image_format,
-            "webp" => image_format,
-            "tiff" => image_format,
-            "bmp" => image_format,
-            "jpeg" => image_format,
-            "svg" => image_format,
-            "avif" => image_format,
-            "gmi" => {
-                let url = if url.contains("gemini://") {
-                    url
-                } else {
-                    &url.replace(".gmi", ".html")
-                };
-                // TODO create a fonction instead use image_format/standard_link_format
-                format!("<a href=\"{url}\">{description}</a><br />")
-            }
-            // unknown extension : standard link
-            _ => standard_link_format,
-        },
+    let is_image = match url.rsplit_once('.') {
+        // try to match a known image extension
+        Some((_reste, extension)) => options
+            .image_extensions
+            .iter()
+            .any(|image_extension| image_extension.eq_ignore_ascii_case(extension)),
         // unable to find an extension : standard link
-        None => standard_link_format,
+        None => false,
+    };
+    // if local gmi file, it will be converted to html
+    let url = match url.strip_suffix(options.gemini_extension.as_str()) {
+        Some(stem) if stem.ends_with('.') && !url.contains("gemini://") => format!("{stem}html"),
+        _ => url.to_string(),
+    };
+    let href = escaped_url(&url);
+    if is_image {
+        let image_html_options = r#"loading="lazy" height="200" sizes="auto, (max-width: 30em) 100vw, (max-width: 50em) 50vw, calc(33vw - 100px)""#;
+        // let image_html_options = r#"loading="lazy" width="200" height="200" sizes="auto, (max-width: 30em) 100vw, (max-width: 50em) 50vw, calc(33vw - 100px)""#;
+        format!(
+            "<a href=\"{href}\"><img {image_html_options} src=\"{href}\" alt=\"{}\" /></a><br />",
+            escaped_attribute(description)
+        )
+    } else {
+        format!("<a href=\"{href}\">{}</a><br />", escaped_text(description))
     }
 }
 
-/// Standard read file
-fn read_from_file(path: &Path) -> Result<String> {
-    debug!("💨 open file {:?}", path);
-    let mut file = File::open(path).map_err(|e| {
-        error!("unable to open file {} : {e:?}", path.to_string_lossy());
-        Gemini2HtmlError
-    })?;
+/// Path standing for the standard input or output
+const STDIO: &str = "-";
+
+/// Standard read file, `-` reads the standard input
+pub(crate) fn read_from_file(path: &Path) -> Result<String> {
     let mut file_content = String::new();
-    file.read_to_string(&mut file_content).map_err(|e| {
-        error!(
-            "unable to convert file {} to string : {e:?}",
-            path.to_string_lossy()
-        );
-        Gemini2HtmlError
-    })?;
+    if path == Path::new(STDIO) {
+        debug!("💨 read standard input");
+        io::stdin()
+            .lock()
+            .read_to_string(&mut file_content)
+            .map_err(Gemini2HtmlError::io(Operation::Read, path))?;
+        return Ok(file_content);
+    }
+    debug!("💨 open file {:?}", path);
+    let mut file = File::open(path).map_err(Gemini2HtmlError::io(Operation::Read, path))?;
+    file.read_to_string(&mut file_content)
+        .map_err(Gemini2HtmlError::io(Operation::Read, path))?;
     Ok(file_content)
 }
 
-/// Standard write to file
-fn write_to_file(path: &Path, content: &str) -> Result<()> {
-    let mut file = File::create(path).map_err(|e| {
-        error!("unable to open file {} : {e:?}", path.to_string_lossy());
-        Gemini2HtmlError
-    })?;
-    file.write_all(content.as_bytes()).map_err(|e| {
-        error!("unable to write to file {} : {e:?}", path.to_string_lossy());
-        Gemini2HtmlError
-    })?;
+/// Standard write to file, `-` writes to the standard output
+pub(crate) fn write_to_file(path: &Path, content: &str) -> Result<()> {
+    if path == Path::new(STDIO) {
+        let mut stdout = io::stdout().lock();
+        return stdout
+            .write_all(content.as_bytes())
+            .and_then(|()| stdout.flush())
+            .map_err(Gemini2HtmlError::io(Operation::Write, path));
+    }
+    let mut file = File::create(path).map_err(Gemini2HtmlError::io(Operation::Write, path))?;
+    file.write_all(content.as_bytes())
+        .map_err(Gemini2HtmlError::io(Operation::Write, path))?;
     Ok(())
 }
 
-/// Main course here, read a gemini content line by line
-/// if a syntax element is found, store the line in the corresponding enum
-/// for lists and preformatted text, use flags for beginning and end tags
-fn parse_gemini(gemini_content: &str) -> (Vec<ParsedGemini>, Option<&str>) {
-    // init the Vec returned
-    let mut parsed_gemini: Vec<ParsedGemini> = Vec::new();
-    let mut title: Option<&str> = None;
-    // create flags for listes and preformatted text
-    let mut flag_list = false;
-    let mut flag_preformatted = false;
-    for line in gemini_content.lines() {
-        // in case some spaces are present before syntax elements
-        let line = line.trim_start();
-        // if the line contain only preformatted tag, we don't need to go further
-        // FIXME does not work if pre include language : ```bash
-        if line == "```" {
-            // not already preformatted ? start !
-            if !flag_preformatted {
-                parsed_gemini.push(ParsedGemini::PreformattedStart);
-                flag_preformatted = true;
-            // another `<pre>` ? end it...
-            } else {
-                parsed_gemini.push(ParsedGemini::PreformattedEnd);
-                flag_preformatted = false;
-            }
-        // if we are in a preformatted block, we need to escape reserved chars
-        // see https://developer.mozilla.org/en-US/docs/Web/HTML/Reference/Elements/pre
-        // `&` -> `&amp;`
-        // `<` and `>` -> `&lt;` and `&gt;`
-        } else if flag_preformatted {
-            // FIXME if last line is preformated or list : tags are not closed !
-            parsed_gemini.push(ParsedGemini::PreformattedText(line.to_string()));
-        } else {
-            // split line in two, matching the first space
-            let (line_prefix, line_rest) = line.split_once(' ').unwrap_or_default();
-
-            // if we were in a list, but not anymore, end it
-            if flag_list && line_prefix != "*" {
-                parsed_gemini.push(ParsedGemini::ListEnd);
-                flag_list = false;
-            }
+/// Footer credits
+const CREDITS: &str = "Generated by gemini2html<br />\n<a href=\"https://github.com/thasos/gemini2html\">https://github.com/thasos/gemini2html</a>";
 
-            // now we search if the first word match a syntax, and push the rest in output Vec
-            // FIXME space is not needed : `=>Link a description` is valid...
-            // same for quotes `>some quote`
-            match line_prefix {
-                "=>" => parsed_gemini.push(ParsedGemini::Link(line_rest.to_string())),
-                "#" => {
-                    title = Some(line_rest);
-                    parsed_gemini.push(ParsedGemini::Heading1(line_rest.to_string()))
-                }
-                "##" => parsed_gemini.push(ParsedGemini::Heading2(line_rest.to_string())),
-                "###" => parsed_gemini.push(ParsedGemini::Heading3(line_rest.to_string())),
-                // a list must begin with tag `<ul>` and start with `</ul>`
-                "*" => {
-                    // begin the list
-                    if !flag_list {
-                        parsed_gemini.push(ParsedGemini::ListStart);
-                        flag_list = true;
-                    }
-                    parsed_gemini.push(ParsedGemini::ListElement(line_rest.to_string()));
-                }
-                ">" => parsed_gemini.push(ParsedGemini::Quote(line_rest.to_string())),
-                // for text, we need all the line
-                _ => parsed_gemini.push(ParsedGemini::Text(line.to_string())),
+/// Image extensions, links to them are displayed with `<img>`
+const IMAGE_EXTENSIONS: [&str; 9] = [
+    "jpg", "png", "gif", "webp", "tiff", "bmp", "jpeg", "svg", "avif",
+];
+
+/// Name of the stylesheet written at the root of a site, with [`Stylesheet::Shared`]
+pub const STYLESHEET_FILE_NAME: &str = "style.css";
+
+/// How pages get their css
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stylesheet {
+    /// In a `<style>` tag of each page
+    Inline,
+    /// In a `style.css` written once at the root of the site, linked by each page
+    /// a single converted file keeps its css inline
+    Shared,
+    /// Link to a stylesheet url, no css is written
+    External(String),
+}
+
+impl Stylesheet {
+    /// Stylesheet from its name in the configuration : `inline`, `shared`,
+    /// or an url with a scheme or an absolute path, anything else is most likely a typo
+    pub fn from_name(name: &str) -> Option<Stylesheet> {
+        let has_scheme = |url: &str| {
+            url.split_once("://").is_some_and(|(scheme, _)| {
+                scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                    && scheme
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+            })
+        };
+        match name {
+            "inline" => Some(Stylesheet::Inline),
+            "shared" => Some(Stylesheet::Shared),
+            url if url.starts_with('/') || has_scheme(url) => {
+                Some(Stylesheet::External(url.to_string()))
             }
+            _ => None,
+        }
+    }
+}
+
+/// Conversion options
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    /// Name of the site, appended to the title of each page
+    pub site_name: Option<String>,
+    /// Url where the site is published, like `https://example.org/`
+    pub base_url: Option<String>,
+    /// Language of the pages, the `lang` attribute of `<html>`
+    pub language: Option<String>,
+    /// Author of the pages, in a `<meta>` tag
+    pub author: Option<String>,
+    /// Html inserted in the footer of each page
+    pub credits: String,
+    /// Built-in stylesheet
+    pub theme: Theme,
+    /// Css replacing the built-in stylesheet
+    pub css: Option<String>,
+    /// Css added after the built-in stylesheet, or after its replacement
+    pub extra_css: Option<String>,
+    /// How pages get their css
+    pub stylesheet: Stylesheet,
+    /// Extension of gemini files, without the dot, links to them are converted to `.html`
+    pub gemini_extension: String,
+    /// Extensions of images, without the dot, case insensitive
+    pub image_extensions: Vec<String>,
+    /// Layout of the pages, the built-in one if `None`
+    pub template: Option<Template>,
+    /// Feeds linked from the head of each page, they need the `base_url`
+    pub feed_links: FeedLinks,
+}
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            site_name: None,
+            base_url: None,
+            language: None,
+            author: None,
+            credits: CREDITS.to_string(),
+            theme: Theme::Default,
+            css: None,
+            extra_css: None,
+            stylesheet: Stylesheet::Inline,
+            gemini_extension: "gmi".to_string(),
+            image_extensions: IMAGE_EXTENSIONS.map(String::from).to_vec(),
+            template: None,
+            feed_links: FeedLinks::default(),
         }
     }
-    (parsed_gemini, title)
+}
+
+/// Informations extracted from a gemini document during conversion
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    /// Title of the page, from the level 1 heading
+    pub title: Option<String>,
+}
+
+/// A gemini document converted to html
+#[derive(Debug, Clone, PartialEq)]
+pub struct Converted {
+    /// The full html page
+    pub html: String,
+    /// What was found in the document, like its title
+    pub metadata: Metadata,
 }
 
 /// Eat parsed gemini Vec, and create a formatted html page
-fn format_gemini_to_html(parsed_gemini: Vec<ParsedGemini>, title: Option<&str>) -> String {
+/// with the template of the options, or the built-in layout
+fn format_gemini_to_html(
+    parsed_gemini: Vec<ParsedGemini>,
+    title: Option<&str>,
+    source: &Source,
+    options: &Options,
+) -> String {
+    let content = html_content(parsed_gemini, options);
+    match &options.template {
+        Some(template) => template.render(&Context {
+            title,
+            content: &content,
+            source,
+            options,
+        }),
+        None => {
+            let headers = html_headers(title, source, options);
+            let footers = html_footers(&options.credits);
+            format!("{headers}{content}{footers}")
+        }
+    }
+}
+
+/// Gemini elements formatted to html, the body of the page
+pub(crate) fn html_content(parsed_gemini: Vec<ParsedGemini>, options: &Options) -> String {
     let mut html_content = String::new();
-    // init html main tags
-    let headers = html_headers(title);
-    // TODO args ? conf file ?
-    let credits = "Generated by gemini2html<br />\n<a href=\"https://github.com/thasos/gemini2html\">https://github.com/thasos/gemini2html</a>";
-    let footers = html_footers(credits);
-    // let's construct html document
-    html_content.push_str(&headers);
+    // highlighter of the current preformatted block, if its language is known
+    #[cfg(feature = "highlight")]
+    let mut highlighter: Option<highlight::Highlighter> = None;
     // insert gemini element formatted to html
     for line in parsed_gemini {
-        html_content.push_str(&line.to_html());
+        #[cfg(feature = "highlight")]
+        match &line {
+            ParsedGemini::PreformattedStart(alt) => {
+                highlighter = alt
+                    .as_deref()
+                    .and_then(language_name)
+                    .and_then(|language| highlight::Highlighter::new(&language));
+            }
+            ParsedGemini::PreformattedText(text) => {
+                if let Some(highlighter) = highlighter.as_mut() {
+                    html_content.push_str(&highlighter.highlight_line(text));
+                    html_content.push('\n');
+                    continue;
+                }
+            }
+            _ => (),
+        }
+        html_content.push_str(&line.to_html(options));
         // insert line feed between each elemets
-        html_content.push('\n');
+        // except after `<code>`, it would add an empty first line to the preformatted block
+        if !matches!(line, ParsedGemini::PreformattedStart(Some(_))) {
+            html_content.push('\n');
+        }
     }
-    html_content.push_str(&footers);
     html_content
 }
 
+/// Convert a gemini content to a html page, without touching the filesystem
+pub fn gemini_to_html(gemini_content: &str, options: &Options) -> Converted {
+    page_to_html(gemini_content, &Source::default(), options)
+}
+
+/// Convert a gemini content to a html page, knowing where it comes from
+pub(crate) fn page_to_html(gemini_content: &str, source: &Source, options: &Options) -> Converted {
+    let (parsed_gemini, title) = parse_gemini(gemini_content);
+    let metadata = Metadata {
+        title: title.map(str::to_string),
+    };
+    let html = format_gemini_to_html(parsed_gemini, title, source, options);
+    Converted { html, metadata }
+}
+
+/// Read gemini content from `reader`, and write the html page to `writer`
+/// errors are reported without path, see [`Gemini2HtmlError::Stream`]
+pub fn convert<R: Read, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    options: &Options,
+) -> Result<Metadata> {
+    let mut gemini_content = String::new();
+    reader
+        .read_to_string(&mut gemini_content)
+        .map_err(|source| Gemini2HtmlError::Stream {
+            operation: Operation::Read,
+            source,
+        })?;
+    let converted = gemini_to_html(&gemini_content, options);
+    writer
+        .write_all(converted.html.as_bytes())
+        .map_err(|source| Gemini2HtmlError::Stream {
+            operation: Operation::Write,
+            source,
+        })?;
+    Ok(converted.metadata)
+}
+
 /// Read file, pass content to the parser, and write the output to the target file
-pub fn convert_gemini_file(gemini_file_path: &Path, target_file: &Path) -> Result<()> {
-    match read_from_file(gemini_file_path) {
-        Ok(gemini_file_content) => {
-            info!("    - 🟢 start file {:?}", gemini_file_path);
-            let (parsed_gemini, title) = parse_gemini(&gemini_file_content);
-            info!("    - 🍽️  parsed");
-            let html_content = format_gemini_to_html(parsed_gemini, title);
-            info!("    - 🎨 converted to html");
-            write_to_file(target_file, &html_content)?;
-            info!("    - 🪦 html saved to file {:?}", target_file);
-        }
-        Err(e) => error!("{}", e),
+/// an unreadable or non utf-8 file is an error, `-` is the standard input or output
+pub fn convert_gemini_file(
+    gemini_file_path: &Path,
+    target_file: &Path,
+    options: &Options,
+) -> Result<()> {
+    // out of a capsule, the page is alone at its root
+    let source_path = gemini_file_path.file_name().map(Path::new);
+    // and there is no shared stylesheet next to it
+    if options.stylesheet == Stylesheet::Shared {
+        let options = Options {
+            stylesheet: Stylesheet::Inline,
+            ..options.clone()
+        };
+        return convert_page(gemini_file_path, source_path, target_file, &options);
     }
+    convert_page(gemini_file_path, source_path, target_file, options)
+}
+
+/// Convert a gemini file, `source_path` is its path relative to the root of the capsule
+pub(crate) fn convert_page(
+    gemini_file_path: &Path,
+    source_path: Option<&Path>,
+    target_file: &Path,
+    options: &Options,
+) -> Result<()> {
+    let gemini_file_content = read_from_file(gemini_file_path)?;
+    info!("    - 🟢 start file {:?}", gemini_file_path);
+    let source = Source {
+        path: source_path,
+        last_modified: fs::metadata(gemini_file_path)
+            .and_then(|metadata| metadata.modified())
+            .ok(),
+    };
+    let converted = page_to_html(&gemini_file_content, &source, options);
+    info!("    - 🎨 converted to html");
+    write_to_file(target_file, &converted.html)?;
+    info!("    - 🪦 html saved to file {:?}", target_file);
     Ok(())
 }
 
@@ -282,65 +562,93 @@ mod tests {
     use super::*;
     use std::fs;
 
+    /// Css of the default options, written out so a change of the stylesheet fails the tests
+    fn default_css() -> String {
+        let css = "html {\n\t/* font-family: sans-serif; */\n\t/* color: #080808; */\n    color: #4d4d4d;\n}\n\nbody {\n\tmax-width: 920px;\n\tmargin: 0 auto;\n\tpadding: 1rem 2rem;\n    background: #fbf6e7;\n}\n\nblockquote {\n\tbackground-color: #eee;\n\tborder-left: 3px solid #444;\n\tmargin: 1rem -1rem 1rem calc(-1rem - 3px);\n\tpadding: 1rem;\n}\n\nblockquote p {\n\tmargin: 0.25rem 0;\n}\n\n.footer {\n\tbackground-color: #e4e4e4;\n\tborder-left: 3px solid #444;\n\tmargin: 1rem -1rem 1rem calc(-1rem - 3px);\n\tpadding: 1rem;\n}\n\nul {\n\tmargin-left: 2rem;\n\tpadding: 0;\n}\n\nli {\n\tpadding: 0;\n}\n\nli:not(:last-child) {\n\tmargin-bottom: 0.5rem;\n}\n\na {\n\tposition: relative;\n\tcolor: #156899;\n}\n\na:visited {\n\tcolor: #5a327e;\n}\n\na:before {\n\tcontent: '⇒';\n\t/* color: #999; */\n\ttext-decoration: none;\n\tfont-weight: bold;\n\tposition: absolute;\n\tleft: -1.25rem;\n}\n\npre {\n\tbackground-color: #e8e2cd;\n\tmargin: 0 -1rem;\n\tpadding: 1rem;\n\toverflow-x: auto;\n}\n\ndetails:not([open]) summary,\ndetails:not([open]) summary a {\n\tcolor: gray;\n}\n\ndetails summary a:before {\n\tdisplay: none;\n}\n\ndl dt {\n\tfont-weight: bold;\n}\n\ndl dt:not(:first-child) {\n\tmargin-top: 0.5rem;\n}\n\n@media(prefers-color-scheme:dark) {\n\thtml {\n\t\tbackground-color: #111;\n\t\tcolor: #eee;\n\t}\n\n\tblockquote {\n\t\tbackground-color: #000;\n\t}\n\n\tpre {\n\t\tbackground-color: #222;\n\t}\n\n\ta {\n\t\tcolor: #0087BD;\n\t}\n\n\ta:visited {\n\t\tcolor: #333399;\n\t}\n}\n\n/* label { */\n/* \tdisplay: block; */\n/* \tfont-weight: bold; */\n/* \tmargin-bottom: 0.5rem; */\n/* } */\n\n/* input { */\n/* \tdisplay: block; */\n/* \tborder: 1px solid #888; */\n/* \tpadding: .375rem; */\n/* \tline-height: 1.25rem; */\n/* \ttransition: border-color .15s ease-in-out,box-shadow .15s ease-in-out; */\n/* \twidth: 100%; */\n/* } */\n\n/* input:focus { */\n/* \toutline: 0; */\n/* \tborder-color: #80bdff; */\n/* \tbox-shadow: 0 0 0 0.2rem rgba(0,123,255,.25); */\n/* } */\n\nfigure {\n\tmargin: 1rem 0;\n}\n\nfigcaption {\n\tfont-size: 0.85rem;\n\tfont-style: italic;\n\tmargin-bottom: 0.25rem;\n}\n".to_string();
+        // the feature appends the highlighting classes
+        #[cfg(feature = "highlight")]
+        let css = format!("{css}{}", include_str!("highlight.css"));
+        css
+    }
+
     #[test]
     fn test_html_headers_and_footers() {
         let footers = html_footers("some footers");
-        assert_eq!(footers, "<p>some footers</p></body>\n</html>\n");
-        let headers = html_headers(Some("A cool title 🪻"));
+        assert_eq!(
+            footers,
+            "<div class=footer>some footers</div></body>\n</html>\n"
+        );
+        let headers = html_headers(
+            Some("A cool title 🪻"),
+            &Source::default(),
+            &Options::default(),
+        );
         assert_eq!(
             headers,
-            "<!doctype html>\n<html>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\" />\n<style>\nhtml {\n\tfont-family: sans-serif;\n\tcolor: #080808;\n}\n\nbody {\n\tmax-width: 920px;\n\tmargin: 0 auto;\n\tpadding: 1rem 2rem;\n}\n\nblockquote {\n\tbackground-color: #eee;\n\tborder-left: 3px solid #444;\n\tmargin: 1rem -1rem 1rem calc(-1rem - 3px);\n\tpadding: 1rem;\n}\n\nul {\n\tmargin-left: 0;\n\tpadding: 0;\n}\n\nli {\n\tpadding: 0;\n}\n\nli:not(:last-child) {\n\tmargin-bottom: 0.5rem;\n}\n\na {\n\tposition: relative;\n}\n\na:before {\n\tcontent: '⇒';\n\tcolor: #999;\n\ttext-decoration: none;\n\tfont-weight: bold;\n\tposition: absolute;\n\tleft: -1.25rem;\n}\n\npre {\n\tbackground-color: #eee;\n\tmargin: 0 -1rem;\n\tpadding: 1rem;\n\toverflow-x: auto;\n}\n\ndetails:not([open]) summary,\ndetails:not([open]) summary a {\n\tcolor: gray;\n}\n\ndetails summary a:before {\n\tdisplay: none;\n}\n\ndl dt {\n\tfont-weight: bold;\n}\n\ndl dt:not(:first-child) {\n\tmargin-top: 0.5rem;\n}\n\n@media(prefers-color-scheme:dark) {\n\thtml {\n\t\tbackground-color: #111;\n\t\tcolor: #eee;\n\t}\n\n\tblockquote {\n\t\tbackground-color: #000;\n\t}\n\n\tpre {\n\t\tbackground-color: #222;\n\t}\n\n\ta {\n\t\tcolor: #0087BD;\n\t}\n\n\ta:visited {\n\t\tcolor: #333399;\n\t}\n}\n\nlabel {\n\tdisplay: block;\n\tfont-weight: bold;\n\tmargin-bottom: 0.5rem;\n}\n\ninput {\n\tdisplay: block;\n\tborder: 1px solid #888;\n\tpadding: .375rem;\n\tline-height: 1.25rem;\n\ttransition: border-color .15s ease-in-out,box-shadow .15s ease-in-out;\n\twidth: 100%;\n}\n\ninput:focus {\n\toutline: 0;\n\tborder-color: #80bdff;\n\tbox-shadow: 0 0 0 0.2rem rgba(0,123,255,.25);\n}\n</style>\n<title>A cool title 🪻</title>\n<body>\n"
+            format!(
+                "<!doctype html>\n<html>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\" />\n<style>\n{}</style>\n<title>A cool title 🪻</title>\n<body>\n",
+                default_css()
+            )
         );
     }
     #[test]
-    fn test_escaped_preformat_text() {
+    fn test_escaped_text() {
         let a_complicated_line = "&;\"🌳";
-        let escaped_line = escaped_preformat_text(a_complicated_line);
+        let escaped_line = escaped_text(a_complicated_line);
         assert_eq!(escaped_line, "&amp;;\"🌳".to_string());
     }
     #[test]
     fn test_write_and_read_file() {
         let content = "some content";
-        let target_path = Path::new("./tests/target_file.test");
+        // not in ./tests, it's walked by site tests at the same time
+        let target_path = &std::env::temp_dir().join("gemini2html_target_file.test");
         // write file
         let write_result = write_to_file(target_path, content);
-        assert_eq!(write_result, Ok(()));
+        assert!(write_result.is_ok());
         // read file
-        let file_content = read_from_file(Path::new("./tests/target_file.test")).unwrap();
+        let file_content = read_from_file(target_path).unwrap();
         assert_eq!(file_content, "some content");
         // clean test file
         fs::remove_file(target_path)
-            .expect("unable to delete test file (./tests/target_file.test)");
+            .expect("unable to delete test file (gemini2html_target_file.test)");
     }
     #[test]
     fn test_format_gemini_to_html() {
         let (parsed_gemini, title) =
             parse_gemini("## heading2\n* tiny list\n```\npreformatted &text\n```\n");
-        let html_content = format_gemini_to_html(parsed_gemini, title);
+        let html_content = format_gemini_to_html(
+            parsed_gemini,
+            title,
+            &Source::default(),
+            &Options::default(),
+        );
         assert_eq!(
             html_content,
-            "<!doctype html>\n<html>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\" />\n<style>\nhtml {\n\tfont-family: sans-serif;\n\tcolor: #080808;\n}\n\nbody {\n\tmax-width: 920px;\n\tmargin: 0 auto;\n\tpadding: 1rem 2rem;\n}\n\nblockquote {\n\tbackground-color: #eee;\n\tborder-left: 3px solid #444;\n\tmargin: 1rem -1rem 1rem calc(-1rem - 3px);\n\tpadding: 1rem;\n}\n\nul {\n\tmargin-left: 0;\n\tpadding: 0;\n}\n\nli {\n\tpadding: 0;\n}\n\nli:not(:last-child) {\n\tmargin-bottom: 0.5rem;\n}\n\na {\n\tposition: relative;\n}\n\na:before {\n\tcontent: '⇒';\n\tcolor: #999;\n\ttext-decoration: none;\n\tfont-weight: bold;\n\tposition: absolute;\n\tleft: -1.25rem;\n}\n\npre {\n\tbackground-color: #eee;\n\tmargin: 0 -1rem;\n\tpadding: 1rem;\n\toverflow-x: auto;\n}\n\ndetails:not([open]) summary,\ndetails:not([open]) summary a {\n\tcolor: gray;\n}\n\ndetails summary a:before {\n\tdisplay: none;\n}\n\ndl dt {\n\tfont-weight: bold;\n}\n\ndl dt:not(:first-child) {\n\tmargin-top: 0.5rem;\n}\n\n@media(prefers-color-scheme:dark) {\n\thtml {\n\t\tbackground-color: #111;\n\t\tcolor: #eee;\n\t}\n\n\tblockquote {\n\t\tbackground-color: #000;\n\t}\n\n\tpre {\n\t\tbackground-color: #222;\n\t}\n\n\ta {\n\t\tcolor: #0087BD;\n\t}\n\n\ta:visited {\n\t\tcolor: #333399;\n\t}\n}\n\nlabel {\n\tdisplay: block;\n\tfont-weight: bold;\n\tmargin-bottom: 0.5rem;\n}\n\ninput {\n\tdisplay: block;\n\tborder: 1px solid #888;\n\tpadding: .375rem;\n\tline-height: 1.25rem;\n\ttransition: border-color .15s ease-in-out,box-shadow .15s ease-in-out;\n\twidth: 100%;\n}\n\ninput:focus {\n\toutline: 0;\n\tborder-color: #80bdff;\n\tbox-shadow: 0 0 0 0.2rem rgba(0,123,255,.25);\n}\n</style>\n<title>some title</title>\n<body>\n<h2>heading2</h2>\n<ul>\n<li>tiny list</li>\n<pre>\npreformatted &amp;text\n</pre>\n<p>some infos</p></body>\n</html>\n"
+            format!(
+                "<!doctype html>\n<html>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\" />\n<style>\n{}</style>\n<title>some title</title>\n<body>\n<h2>heading2</h2>\n<ul>\n<li>tiny list</li>\n</ul>\n<pre>\npreformatted &amp;text\n</pre>\n<div class=footer>Generated by gemini2html<br />\n<a href=\"https://github.com/thasos/gemini2html\">https://github.com/thasos/gemini2html</a></div></body>\n</html>\n",
+                default_css()
+            )
         );
     }
     #[test]
     fn test_html_link() {
         // simple
         let simple_link = "protocol://fqdn/path";
-        let htmled_link = html_link(simple_link);
+        let htmled_link = html_link(simple_link, &Options::default());
         assert_eq!(
             htmled_link,
             "<a href=\"protocol://fqdn/path\">protocol://fqdn/path</a><br />".to_string()
         );
         // description
         let simple_link_with_description = "protocol://fqdn/path some nice description";
-        let htmled_link = html_link(simple_link_with_description);
+        let htmled_link = html_link(simple_link_with_description, &Options::default());
         assert_eq!(
             htmled_link,
             "<a href=\"protocol://fqdn/path\">some nice description</a><br />".to_string()
         );
         // image
         let simple_link_to_image = "protocol://fqdn/path.png";
-        let htmled_link = html_link(simple_link_to_image);
+        let htmled_link = html_link(simple_link_to_image, &Options::default());
         assert_eq!(
             htmled_link,
             "<a href=\"protocol://fqdn/path.png\"><img loading=\"lazy\" height=\"200\" sizes=\"auto, (max-width: 30em) 100vw, (max-width: 50em) 50vw, calc(33vw - 100px)\" src=\"protocol://fqdn/path.png\" alt=\"protocol://fqdn/path.png\" /></a><br />".to_string()
@@ -348,17 +656,380 @@ mod tests {
         // image with description
         let simple_link_to_image_with_description =
             "protocol://fqdn/path.png some nice image description";
-        let htmled_link = html_link(simple_link_to_image_with_description);
+        let htmled_link = html_link(simple_link_to_image_with_description, &Options::default());
         assert_eq!(
             htmled_link,
             "<a href=\"protocol://fqdn/path.png\"><img loading=\"lazy\" height=\"200\" sizes=\"auto, (max-width: 30em) 100vw, (max-width: 50em) 50vw, calc(33vw - 100px)\" src=\"protocol://fqdn/path.png\" alt=\"some nice image description\" /></a><br />".to_string()
         );
         // first characters are spaces
         let simple_link_with_spaces = "    protocol://fqdn/path";
-        let htmled_link = html_link(simple_link_with_spaces);
+        let htmled_link = html_link(simple_link_with_spaces, &Options::default());
         assert_eq!(
             htmled_link,
             "<a href=\"protocol://fqdn/path\">protocol://fqdn/path</a><br />".to_string()
         );
     }
+    #[test]
+    fn test_gemini_to_html() {
+        let options = Options {
+            credits: "some credits".to_string(),
+            ..Options::default()
+        };
+        let converted = gemini_to_html("# a title\ntext\n", &options);
+        assert_eq!(converted.metadata.title, Some("a title".to_string()));
+        assert_eq!(
+            converted.html,
+            format!(
+                "{}<h1>a title</h1>\n<p>text</p>\n{}",
+                html_headers(Some("a title"), &Source::default(), &Options::default()),
+                html_footers("some credits")
+            )
+        );
+    }
+    #[test]
+    fn test_convert_reader_to_writer() {
+        let mut output = Vec::new();
+        let metadata = convert("## no title".as_bytes(), &mut output, &Options::default()).unwrap();
+        assert_eq!(metadata, Metadata::default());
+        let html = String::from_utf8(output).unwrap();
+        assert_eq!(
+            html,
+            gemini_to_html("## no title", &Options::default()).html
+        );
+        // a stream has no path
+        let error = convert(&[0xff][..], &mut Vec::new(), &Options::default()).unwrap_err();
+        assert_eq!(error.operation(), Some(Operation::Read));
+        assert_eq!(error.path(), None);
+    }
+    #[test]
+    fn test_language_name() {
+        assert_eq!(language_name("bash"), Some("bash".to_string()));
+        assert_eq!(language_name("C++"), Some("c++".to_string()));
+        assert_eq!(
+            language_name("objective-c"),
+            Some("objective-c".to_string())
+        );
+        assert_eq!(language_name("ASCII art of a cat"), None);
+        // a single word is not always a language
+        assert_eq!(language_name("Output"), None);
+        assert_eq!(language_name("Figure"), None);
+        assert_eq!(language_name("ASCII-art"), None);
+        assert_eq!(language_name("42"), None);
+        assert_eq!(language_name(""), None);
+    }
+    #[test]
+    fn test_preformatted_alt_text() {
+        let (parsed_gemini, title) =
+            parse_gemini("```Haskell\nmain = pure ()\n```\n```a cat\n=^.^=\n```\n");
+        let html_content = format_gemini_to_html(
+            parsed_gemini,
+            title,
+            &Source::default(),
+            &Options::default(),
+        );
+        assert_eq!(
+            html_content,
+            format!(
+                "{}<figure>\n<figcaption>Haskell</figcaption>\n<pre aria-label=\"Haskell\"><code class=\"language-haskell\">main = pure ()\n</code></pre>\n</figure>\n<figure>\n<figcaption>a cat</figcaption>\n<pre aria-label=\"a cat\"><code>=^.^=\n</code></pre>\n</figure>\n{}",
+                html_headers(None, &Source::default(), &Options::default()),
+                html_footers(CREDITS)
+            )
+        );
+    }
+    #[cfg(feature = "highlight")]
+    #[test]
+    fn test_highlighted_preformatted_block() {
+        let (parsed_gemini, title) =
+            parse_gemini("```rust\nlet a = 1;\n```\n```\nlet a = 1;\n```\n");
+        let html_content = format_gemini_to_html(
+            parsed_gemini,
+            title,
+            &Source::default(),
+            &Options::default(),
+        );
+        assert!(html_content.contains(
+            "<code class=\"language-rust\"><span class=\"hl-keyword\">let</span> a = <span class=\"hl-number\">1</span>;\n</code>"
+        ));
+        assert!(html_content.contains("<pre>\nlet a = 1;\n</pre>"));
+    }
+    #[test]
+    fn test_quotes_in_blockquote() {
+        let (parsed_gemini, title) = parse_gemini("> one\n>two\ntext\n");
+        let html_content = format_gemini_to_html(
+            parsed_gemini,
+            title,
+            &Source::default(),
+            &Options::default(),
+        );
+        assert_eq!(
+            html_content,
+            format!(
+                "{}<blockquote>\n<p>one</p>\n<p>two</p>\n</blockquote>\n<p>text</p>\n{}",
+                html_headers(None, &Source::default(), &Options::default()),
+                html_footers(CREDITS)
+            )
+        );
+    }
+    #[test]
+    fn test_escaped_attribute_and_url() {
+        assert_eq!(
+            escaped_attribute("\"><script>'"),
+            "&quot;&gt;&lt;script&gt;&#39;"
+        );
+        assert_eq!(
+            escaped_url("https://fqdn/?a=1&b=\"2\""),
+            "https://fqdn/?a=1&amp;b=&quot;2&quot;"
+        );
+        assert_eq!(escaped_url("javascript:alert(1)"), "#");
+        assert_eq!(escaped_url(" JavaScript:alert(1)"), "#");
+        assert_eq!(escaped_url("java\tscript:alert(1)"), "#");
+        assert_eq!(escaped_url("data:text/html,<p>"), "#");
+        assert_eq!(
+            escaped_url("relative/path:with/colon"),
+            "relative/path:with/colon"
+        );
+    }
+    #[test]
+    fn test_hostile_gemtext_cannot_inject_markup() {
+        let hostile_gemini = concat!(
+            "# <script>alert('title')</script>\n",
+            "<script>alert('text')</script>\n",
+            "## <img src=x onerror=alert(1)>\n",
+            "### </h3><script>\n",
+            "* <b>item</b>\n",
+            "> </blockquote><script>\n",
+            "=> https://fqdn/\"onmouseover=\"alert(1) <i>description</i>\n",
+            "=> https://fqdn/\"><script>.png \"/><script>\n",
+            "=> javascript:alert(1) click me\n",
+            "```\"><script>\n",
+            "</pre><script>\n",
+            "```\n",
+        );
+        let converted = gemini_to_html(hostile_gemini, &Options::default());
+        let html_body = converted
+            .html
+            .split_once("<body>")
+            .map(|(_, body)| body)
+            .unwrap();
+        // the only tags left are the ones from the renderer
+        assert!(!html_body.contains("<script"));
+        assert!(!html_body.contains("<img src=x"));
+        assert!(!html_body.contains("<b>"));
+        assert!(!html_body.contains("<i>"));
+        assert!(!html_body.contains("\"onmouseover"));
+        assert!(!html_body.contains("javascript:"));
+        assert!(html_body.contains("<h1>&lt;script&gt;alert('title')&lt;/script&gt;</h1>"));
+        assert!(html_body.contains(
+            "<a href=\"https://fqdn/&quot;onmouseover=&quot;alert(1)\">&lt;i&gt;description&lt;/i&gt;</a><br />"
+        ));
+        assert!(html_body.contains("<a href=\"#\">click me</a><br />"));
+        assert!(html_body.contains("<pre aria-label=\"&quot;&gt;&lt;script&gt;\">"));
+        // the title is escaped too
+        assert!(
+            converted
+                .html
+                .contains("<title>&lt;script&gt;alert('title')&lt;/script&gt;</title>")
+        );
+    }
+
+    /// Check that all tags of a html page are balanced and properly nested
+    /// returns the first problem found
+    fn check_well_formed(html: &str) -> std::result::Result<(), String> {
+        const VOID_ELEMENTS: [&str; 4] = ["meta", "br", "img", "link"];
+        let mut open_tags: Vec<String> = Vec::new();
+        let mut rest = html;
+        while let Some(start) = rest.find('<') {
+            let end = rest[start..]
+                .find('>')
+                .ok_or(format!("unterminated tag at {}", &rest[start..]))?;
+            let tag = &rest[start + 1..start + end];
+            rest = &rest[start + end + 1..];
+            if tag.starts_with('!') {
+                continue;
+            }
+            if let Some(closing) = tag.strip_prefix('/') {
+                match open_tags.pop() {
+                    Some(open) if open == closing => (),
+                    open => return Err(format!("</{closing}> closes {open:?}")),
+                }
+            } else {
+                let name = tag.split_whitespace().next().unwrap_or_default();
+                if name.is_empty() || name.contains(|c: char| !c.is_ascii_alphanumeric()) {
+                    return Err(format!("invalid tag <{tag}>"));
+                }
+                if !VOID_ELEMENTS.contains(&name) && !tag.ends_with('/') {
+                    open_tags.push(name.to_string());
+                }
+                // css is not html, skip it
+                if name == "style" {
+                    let style_end = rest.find("</style>").ok_or("unclosed <style>")?;
+                    rest = &rest[style_end..];
+                }
+            }
+        }
+        match open_tags.is_empty() {
+            true => Ok(()),
+            false => Err(format!("unclosed tags {open_tags:?}")),
+        }
+    }
+    #[test]
+    fn test_generated_pages_are_well_formed() {
+        let documents = [
+            // blocks left open at the end of the document
+            "* a list at the end",
+            "> a quote at the end",
+            "```\na preformatted block at the end",
+            "```bash\necho a preformatted block with alt text at the end",
+            // every block transition
+            "* list\n> quote\n```\npre\n```\n* list\n```alt\npre\n```\n> quote\n* list\n=> link\n",
+            "> quote\n```\n* not a list\n> not a quote\n```\n## heading\n* list\n# title\n",
+            "```\n```\n```alt\n```\n",
+            "",
+            // hostile content
+            "# </h1></body>\n* </ul>\n> </blockquote>\n```</pre>\n</code></pre>\n",
+            include_str!("../tests/gemini_file.gmi"),
+            include_str!("../tests/subdir/subfile.gmi"),
+        ];
+        for document in documents {
+            let html = gemini_to_html(document, &Options::default()).html;
+            assert_eq!(check_well_formed(&html), Ok(()), "{document:?}");
+        }
+    }
+    #[test]
+    fn test_check_well_formed() {
+        assert!(check_well_formed("<p><br /><b></b></p>").is_ok());
+        assert!(check_well_formed("<ul><li></li>").is_err());
+        assert!(check_well_formed("<ul><li></ul></li>").is_err());
+    }
+    #[test]
+    fn test_convert_gemini_file_reports_read_errors() {
+        let target_path = std::env::temp_dir().join("gemini2html_never_written.html");
+        let result = convert_gemini_file(
+            Path::new("./tests/not_here.gmi"),
+            &target_path,
+            &Options::default(),
+        );
+        let error = result.unwrap_err();
+        assert_eq!(error.operation(), Some(Operation::Read));
+        assert_eq!(error.path(), Some(Path::new("./tests/not_here.gmi")));
+        assert!(!target_path.exists());
+    }
+    #[test]
+    fn test_html_link_with_custom_extension() {
+        let options = Options {
+            gemini_extension: "gemini".to_string(),
+            ..Options::default()
+        };
+        assert_eq!(
+            html_link("post.gemini a post", &options),
+            "<a href=\"post.html\">a post</a><br />"
+        );
+        assert_eq!(
+            html_link("post.gmi a post", &options),
+            "<a href=\"post.gmi\">a post</a><br />"
+        );
+        assert_eq!(
+            html_link("gemini://fqdn/post.gemini a post", &options),
+            "<a href=\"gemini://fqdn/post.gemini\">a post</a><br />"
+        );
+    }
+    #[test]
+    fn test_custom_css() {
+        let options = Options {
+            css: Some("body { color: red; }\n".to_string()),
+            ..Options::default()
+        };
+        let converted = gemini_to_html("text", &options);
+        assert!(
+            converted
+                .html
+                .contains("<style>\nbody { color: red; }\n</style>")
+        );
+    }
+    #[test]
+    fn test_site_options() {
+        let options = Options {
+            site_name: Some("My capsule".to_string()),
+            language: Some("fr".to_string()),
+            author: Some("Me & \"you\"".to_string()),
+            image_extensions: vec!["JXL".to_string()],
+            ..Options::default()
+        };
+        let headers = html_headers(Some("A post"), &Source::default(), &options);
+        assert!(headers.starts_with("<!doctype html>\n<html lang=\"fr\">\n"));
+        assert!(
+            headers.contains("<meta name=\"author\" content=\"Me &amp; &quot;you&quot;\" />\n")
+        );
+        assert!(headers.contains("<title>A post - My capsule</title>"));
+        assert!(
+            html_headers(None, &Source::default(), &options).contains("<title>My capsule</title>")
+        );
+        assert!(
+            html_headers(Some("My capsule"), &Source::default(), &options)
+                .contains("<title>My capsule</title>")
+        );
+        assert!(html_link("photo.jxl", &options).contains("<img "));
+        assert!(!html_link("photo.png", &options).contains("<img "));
+        // feed readers find the feed from any page
+        let options = Options {
+            base_url: Some("https://example.org".to_string()),
+            feed_links: FeedLinks {
+                atom: true,
+                rss: false,
+            },
+            ..options
+        };
+        assert!(html_headers(None, &Source::default(), &options).contains(
+            "<link rel=\"alternate\" type=\"application/atom+xml\" href=\"https://example.org/atom.xml\" />\n"
+        ));
+    }
+    #[test]
+    fn test_template_layout() {
+        let options = Options {
+            template: Some(Template::parse("{{ source_path }}|{{ title }}|{{ content }}").unwrap()),
+            ..Options::default()
+        };
+        assert_eq!(
+            gemini_to_html("# a <title>", &options).html,
+            "|a &lt;title&gt;|<h1>a &lt;title&gt;</h1>\n"
+        );
+        let target_path = std::env::temp_dir().join("gemini2html_template.html");
+        convert_gemini_file(Path::new("./tests/gemini_file.gmi"), &target_path, &options).unwrap();
+        let html = fs::read_to_string(&target_path).unwrap();
+        assert!(html.starts_with("gemini_file.gmi|"));
+        fs::remove_file(&target_path).expect("unable to purge test file");
+    }
+    #[test]
+    fn test_stylesheet_from_name() {
+        assert_eq!(Stylesheet::from_name("shared"), Some(Stylesheet::Shared));
+        assert_eq!(
+            Stylesheet::from_name("https://example.org/main.css"),
+            Some(Stylesheet::External(
+                "https://example.org/main.css".to_string()
+            ))
+        );
+        assert_eq!(
+            Stylesheet::from_name("/css/main.css"),
+            Some(Stylesheet::External("/css/main.css".to_string()))
+        );
+        assert_eq!(Stylesheet::from_name("shraed"), None);
+        assert_eq!(Stylesheet::from_name("css/main.css"), None);
+        assert_eq!(Stylesheet::from_name("://main.css"), None);
+    }
+    #[test]
+    fn test_theme() {
+        let options = Options {
+            theme: Theme::Terminal,
+            ..Options::default()
+        };
+        let converted = gemini_to_html("text", &options);
+        assert!(converted.html.contains(Theme::Terminal.css()));
+        assert!(!converted.html.contains(Theme::Default.css()));
+        // a custom css replaces the theme
+        let options = Options {
+            css: Some("p {}\n".to_string()),
+            ..options
+        };
+        assert!(!stylesheet(&options).contains(Theme::Terminal.css()));
+    }
 }
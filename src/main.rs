@@ -1,199 +1,530 @@
 #![forbid(unsafe_code)]
-use gemini2html::Gemini2HtmlError;
-use gemini2html::convert_gemini_file;
+use gemini2html::config::Config;
+use gemini2html::serve::serve;
+use gemini2html::site::{SiteOptions, build_site, check_site, default_jobs, prune};
+#[cfg(target_os = "linux")]
+use gemini2html::watch::watch;
+use gemini2html::{Gemini2HtmlError, Operation, Options, Stylesheet, Theme, convert_gemini_file};
 
-use log::{debug, error, info};
+use log::{LevelFilter, debug, error, info};
 use std::env;
-use std::ffi::OsStr;
+use std::fmt;
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-/// Walks through a tree directory, recreate arborescence, convert gemini files, and copy the rest
-/// it's a recursive function, but we always need the original ancestor (it's ugly, I know...)
-fn convert_tree(
-    // TODO find a way to retrieve ancestor
-    // with `ancestors()` ? or `components()` ?
-    // see https://doc.rust-lang.org/std/path/struct.Path.html#method.ancestors
-    ancestor: &Path,
-    source_tree_directory: &Path,
-    target_tree_directory: &Path,
-) -> Result<(), Gemini2HtmlError> {
-    let tree = fs::read_dir(source_tree_directory).map_err(|e| {
-        error!("unable to read source directory : {e:?}");
-        Gemini2HtmlError
-    })?;
-    // used further, should be in args
-    let gemini_extension = OsStr::new("gmi");
-    // start looping on dir entries
-    for direntry in tree {
-        match direntry {
-            Ok(direntry) => {
-                // we only need path
-                let direntry = direntry.path();
-                // recreate directory tree
-                if direntry.is_dir() {
-                    info!("- 🗃️ found directory {:?}", &direntry);
-                    let target_directory =
-                        replace_ancestor(ancestor, target_tree_directory, &direntry)?;
-                    if !target_directory.is_dir() {
-                        info!("- 🦢 need to create {:?}", &target_directory);
-                        fs::create_dir(&target_directory).map_err(|e| {
-                            error!("unable to create directory : {e:?}");
-                            Gemini2HtmlError
-                        })?;
-                    }
-                    convert_tree(ancestor, &direntry, target_tree_directory)?;
-                } else {
-                    info!("- 🗒 found file {:?}", direntry);
-                    // convert gemini file (`.gmi` extension)
-                    if direntry.extension() == Some(gemini_extension) {
-                        info!("- ⏩ convert to html");
-                        // we need to change file extension
-                        let mut target_html_file_path = direntry.clone();
-                        let _ = target_html_file_path.set_extension("html");
-                        let target_file = replace_ancestor(
-                            ancestor,
-                            target_tree_directory,
-                            &target_html_file_path,
-                        )?;
-                        convert_gemini_file(&direntry, &target_file)?;
-                    // copy other files (images...)
-                    } else {
-                        let target_file =
-                            replace_ancestor(ancestor, target_tree_directory, &direntry)?;
-                        info!("- 🍝 copy non gemini file : {:?}", target_file);
-                        fs::copy(direntry, target_file).map_err(|e| {
-                            error!("unable to create directory : {e:?}");
-                            Gemini2HtmlError
-                        })?;
-                    }
-                }
+const USAGE: &str = "usage : gemini2html [options] <command> [arguments]
+
+commands :
+  build <source directory> <target directory>  convert a whole capsule, the default command
+  convert <gemini file> [<html file>]          convert a single file, `-` is stdin or stdout
+                                               (default : stdout), also `gemini2html -`
+  check <source directory>                     check gemini files and local links, write nothing
+  serve <directory>                            serve a built site on http://127.0.0.1:<port>
+  watch <source directory> <target directory>  build, then rebuild after each change (Linux only)
+  new <directory>                              create a new capsule
+
+options :
+  -c, --config <file>      configuration file, every option below can be set there
+                           (default : gemini2html.toml at the root of the capsule)
+  -e, --extension <ext>    extension of gemini files (default : gmi)
+      --credits <html>     footer of each page
+      --theme <name>       built-in style : default, terminal, high-contrast or print
+      --css <file>         css file replacing the built-in style
+      --extra-css <file>   css file added after the built-in style
+      --stylesheet <mode>  inline (default), shared for a single style.css,
+                           or the url of a stylesheet, absolute or with a scheme
+      --template <file>    html template of the pages
+      --drafts             build drafts too, like post.draft.gmi
+  -f, --force              build every file, not only the ones changed since the previous build
+      --clean              remove the outputs of previous builds whose source was removed
+      --dry-run            list these outputs, without removing them
+  -j, --jobs <number>      number of threads of a build (default : one per cpu)
+  -l, --log-level <level>  error, warn, info, debug or trace (default : info)
+  -p, --port <port>        port used by serve (default : 8080)
+  -h, --help               print this help
+  -V, --version            print version";
+
+/// What to do
+#[derive(Debug, PartialEq)]
+enum Command {
+    Build { source: PathBuf, target: PathBuf },
+    Convert { source: PathBuf, target: PathBuf },
+    Check { source: PathBuf },
+    Serve { directory: PathBuf },
+    Watch { source: PathBuf, target: PathBuf },
+    New { directory: PathBuf },
+    Help,
+    Version,
+}
+
+/// Parsed command line
+#[derive(Debug, PartialEq)]
+struct Args {
+    command: Command,
+    /// Configuration file given with `--config`
+    config_file: Option<PathBuf>,
+    /// Settings given as flags, they override the configuration file
+    flags: Config,
+}
+
+/// Outputs of previous builds whose source was removed
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Clean {
+    /// Kept, their number is logged
+    Keep,
+    /// Listed, with `--dry-run`
+    List,
+    /// Removed, with `--clean`
+    Remove,
+}
+
+impl Clean {
+    /// From the flags or the configuration file, a dry run wins
+    fn from_config(config: &Config) -> Clean {
+        match (config.dry_run, config.clean) {
+            (Some(true), _) => Clean::List,
+            (_, Some(true)) => Clean::Remove,
+            _ => Clean::Keep,
+        }
+    }
+}
+
+/// Failure of the command line
+#[derive(Debug)]
+enum CliError {
+    /// Invalid command line arguments, with the usage
+    Usage(String),
+    /// The command failed
+    Failed(Gemini2HtmlError),
+}
+
+impl From<Gemini2HtmlError> for CliError {
+    fn from(e: Gemini2HtmlError) -> Self {
+        CliError::Failed(e)
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::Usage(usage) => write!(f, "{usage}"),
+            CliError::Failed(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// Very simple args parser
+/// options can be anywhere, without command `build` is used
+fn parse_args(args: &[String]) -> Result<Args, CliError> {
+    let usage_error = |message: String| CliError::Usage(format!("{message}\n\n{USAGE}"));
+    let mut positionals: Vec<&str> = Vec::new();
+    let mut config_file = None;
+    let mut flags = Config::default();
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        // `--flag=value` or `--flag value`
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if arg.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next().cloned())
+                .ok_or_else(|| usage_error(format!("missing value for {flag}")))
+        };
+        match flag {
+            "-h" | "--help" => return Ok(Args::new(Command::Help)),
+            "-V" | "--version" => return Ok(Args::new(Command::Version)),
+            "-c" | "--config" => config_file = Some(PathBuf::from(value()?)),
+            "-e" | "--extension" => flags.extension = Some(value()?),
+            "--credits" => flags.credits = Some(value()?),
+            "--css" => flags.css = Some(PathBuf::from(value()?)),
+            "--theme" => {
+                let name = value()?;
+                flags.theme = Some(
+                    Theme::from_name(&name)
+                        .ok_or_else(|| usage_error(format!("unknown theme {name}")))?,
+                )
+            }
+            "--extra-css" => flags.extra_css = Some(PathBuf::from(value()?)),
+            "--stylesheet" => {
+                let name = value()?;
+                flags.stylesheet = Some(
+                    Stylesheet::from_name(&name)
+                        .ok_or_else(|| usage_error(format!("unknown stylesheet {name}")))?,
+                )
+            }
+            "--template" => flags.template = Some(PathBuf::from(value()?)),
+            "--drafts" => flags.drafts = Some(true),
+            "-f" | "--force" => flags.force = Some(true),
+            "--clean" => flags.clean = Some(true),
+            "--dry-run" => flags.dry_run = Some(true),
+            "-j" | "--jobs" => {
+                let jobs = value()?;
+                flags.jobs = Some(
+                    jobs.parse()
+                        .ok()
+                        .filter(|jobs| *jobs > 0)
+                        .ok_or_else(|| usage_error(format!("invalid number of jobs {jobs}")))?,
+                )
+            }
+            "-l" | "--log-level" => flags.log_level = Some(value()?),
+            "-p" | "--port" => {
+                let port = value()?;
+                flags.port = Some(
+                    port.parse()
+                        .map_err(|_| usage_error(format!("invalid port {port}")))?,
+                )
             }
-            Err(e) => {
-                error!("unable to list all files in directory {e}");
-                return Err(Gemini2HtmlError);
+            _ if flag.starts_with('-') && flag.len() > 1 => {
+                return Err(usage_error(format!("unknown option {flag}")));
             }
+            _ => positionals.push(arg),
         }
     }
-    Ok(())
+    let command = match positionals.as_slice() {
+        ["build", source, target] => Command::Build {
+            source: PathBuf::from(source),
+            target: PathBuf::from(target),
+        },
+        ["convert", source, target] => Command::Convert {
+            source: PathBuf::from(source),
+            target: PathBuf::from(target),
+        },
+        ["convert", source] => Command::Convert {
+            source: PathBuf::from(source),
+            target: PathBuf::from("-"),
+        },
+        ["check", source] => Command::Check {
+            source: PathBuf::from(source),
+        },
+        ["serve", directory] => Command::Serve {
+            directory: PathBuf::from(directory),
+        },
+        ["watch", source, target] => Command::Watch {
+            source: PathBuf::from(source),
+            target: PathBuf::from(target),
+        },
+        ["new", directory] => Command::New {
+            directory: PathBuf::from(directory),
+        },
+        // standard input or output : a single document is converted
+        ["-"] => Command::Convert {
+            source: PathBuf::from("-"),
+            target: PathBuf::from("-"),
+        },
+        [source, target] if *source == "-" || *target == "-" => Command::Convert {
+            source: PathBuf::from(source),
+            target: PathBuf::from(target),
+        },
+        // no command, like the first versions of gemini2html
+        [source, target] => Command::Build {
+            source: PathBuf::from(source),
+            target: PathBuf::from(target),
+        },
+        [] => return Err(usage_error("not enough arguments".to_string())),
+        _ => {
+            return Err(usage_error(format!(
+                "invalid arguments : {}",
+                positionals.join(" ")
+            )));
+        }
+    };
+    debug!("command : {:?}", command);
+    Ok(Args {
+        command,
+        config_file,
+        flags,
+    })
 }
 
-/// Replace ancestor directory by another in a Path
-fn replace_ancestor(
-    source_ancestor: &Path,
-    target_ancestor: &Path,
-    path: &Path,
-) -> Result<PathBuf, Gemini2HtmlError> {
-    let target_without_ancestor = path.strip_prefix(source_ancestor).map_err(|e| {
-        error!("unable to read source directory : {e:?}");
-        Gemini2HtmlError
-    })?;
-    let final_target = Path::new(target_ancestor).join(target_without_ancestor);
-    Ok(final_target)
+impl Command {
+    /// Root of the capsule used by the command, where its configuration file is looked for
+    /// the standard input is not in a capsule, its output must not depend on the current directory
+    fn capsule_directory(&self) -> Option<&Path> {
+        match self {
+            Command::Build { source, .. }
+            | Command::Watch { source, .. }
+            | Command::Check { source } => Some(source),
+            Command::Convert { source, .. } if source == Path::new("-") => None,
+            Command::Convert { source, .. } => source.parent(),
+            _ => None,
+        }
+    }
 }
 
-/// Very simple args parser
-fn parse_args(args: &[String]) -> Result<(&Path, &Path), Gemini2HtmlError> {
-    if args.len() < 3 {
-        error!(
-            "not enough arguments, usage : gemini2html <source directory> <destination directory>"
-        );
-        return Err(Gemini2HtmlError);
+impl Args {
+    fn new(command: Command) -> Args {
+        Args {
+            command,
+            config_file: None,
+            flags: Config::default(),
+        }
     }
-    let source = &args[1];
-    let target = &args[2];
-    debug!("source directory : {:?}", source);
-    debug!("target directory :  {:?}", target);
-    let source_tree_directory = Path::new(source);
-    let target_tree_directory = Path::new(target);
-    Ok((source_tree_directory, target_tree_directory))
+}
+
+/// Parse a log level name
+fn log_level(level: &str) -> Result<LevelFilter, CliError> {
+    level
+        .parse()
+        .map_err(|_| CliError::Usage(format!("invalid log level {level}\n\n{USAGE}")))
 }
 
 /// Here is the magic
-fn main() -> Result<(), Gemini2HtmlError> {
+fn main() -> ExitCode {
+    // everything is logged until the configured level is known
+    // logs go to stderr, stdout may be the converted page
     env_logger::Builder::default()
-        .filter_level(log::LevelFilter::Info)
+        .target(env_logger::Target::Stderr)
+        .filter_level(LevelFilter::Trace)
         .init();
+    log::set_max_level(LevelFilter::Info);
+    let args: Vec<String> = env::args().collect();
+    match run(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            error!("😢 {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Run the command given in args, return false if it failed
+fn run(args: &[String]) -> Result<bool, CliError> {
+    let args = parse_args(args)?;
+    let config = match &args.config_file {
+        Some(config_file) => args.flags.or(Config::load(config_file)?),
+        // without `--config`, the configuration of the capsule is used if there is one
+        None => match args.command.capsule_directory() {
+            Some(directory) => args.flags.or(Config::for_capsule(directory)?),
+            None => args.flags,
+        },
+    };
+    if let Some(level) = &config.log_level {
+        log::set_max_level(log_level(level)?);
+    }
+    match args.command {
+        Command::Help => {
+            println!("{USAGE}");
+            Ok(true)
+        }
+        Command::Version => {
+            println!("gemini2html {}", env!("CARGO_PKG_VERSION"));
+            Ok(true)
+        }
+        // a single file given to build is converted
+        Command::Build { source, target } if source.is_file() => {
+            Ok(convert(&source, &target, &config.options()?)?)
+        }
+        Command::Build { source, target } => {
+            let jobs = config.jobs.unwrap_or_else(default_jobs);
+            Ok(build(
+                &source,
+                &target,
+                &config.options()?,
+                &config.site_options(),
+                jobs,
+                config.force.unwrap_or_default(),
+                Clean::from_config(&config),
+            )?)
+        }
+        Command::Convert { source, target } => Ok(convert(&source, &target, &config.options()?)?),
+        Command::Check { source } => {
+            Ok(check(&source, &config.options()?, &config.site_options())?)
+        }
+        Command::Serve { directory } => {
+            serve(&directory, config.port.unwrap_or(8080))?;
+            Ok(true)
+        }
+        #[cfg(target_os = "linux")]
+        Command::Watch { source, target } => {
+            prepare_directories(&source, &target)?;
+            let jobs = config.jobs.unwrap_or_else(default_jobs);
+            watch(
+                &source,
+                &target,
+                &config.options()?,
+                &config.site_options(),
+                jobs,
+            )?;
+            Ok(true)
+        }
+        #[cfg(not(target_os = "linux"))]
+        Command::Watch { .. } => Err(CliError::Usage(
+            "watch needs inotify, it is only available on Linux".to_string(),
+        )),
+        Command::New { directory } => {
+            new_capsule(&directory)?;
+            info!("🌱 new capsule created in {:?}", directory);
+            Ok(true)
+        }
+    }
+}
+
+/// Source directory must exist
+fn check_source_directory(source_tree_directory: &Path) -> Result<(), Gemini2HtmlError> {
+    match source_tree_directory.is_dir() {
+        true => Ok(()),
+        false => Err(Gemini2HtmlError::Io {
+            operation: Operation::ReadDir,
+            path: source_tree_directory.to_path_buf(),
+            source: io::Error::new(io::ErrorKind::NotFound, "source directory not found"),
+        }),
+    }
+}
+
+/// Source directory must exist, target directory is created if needed
+fn prepare_directories(
+    source_tree_directory: &Path,
+    target_tree_directory: &Path,
+) -> Result<(), Gemini2HtmlError> {
+    check_source_directory(source_tree_directory)?;
+    // create target directory if not present
+    if !target_tree_directory.is_dir() {
+        info!("ℹ️  target directory not found, create it");
+        fs::create_dir(target_tree_directory).map_err(Gemini2HtmlError::io(
+            Operation::CreateDir,
+            target_tree_directory,
+        ))?;
+    }
+    Ok(())
+}
+
+/// Convert the source tree, and print a summary of the build
+fn build(
+    source_tree_directory: &Path,
+    target_tree_directory: &Path,
+    options: &Options,
+    site_options: &SiteOptions,
+    jobs: usize,
+    force: bool,
+    clean: Clean,
+) -> Result<bool, Gemini2HtmlError> {
     info!("🚀 start gemini2html");
     info!("---------------------");
-    // read directory from args
-    let args: Vec<String> = env::args().collect();
-    let (source_tree_directory, target_tree_directory) = parse_args(&args)?;
-    // source directory exists ?
-    if source_tree_directory.is_dir() {
-        // create target directory if not present
-        if !target_tree_directory.is_dir() {
-            info!("ℹ️  target directory not found, create it");
-            fs::create_dir(target_tree_directory).map_err(|e| {
-                error!("unable to create target directory : {e:?}");
-                Gemini2HtmlError
-            })?;
-        }
-        // let's go hike
-        info!(
-            "🚶 walking source directory {:?} and create tree 🌳",
-            source_tree_directory
+    prepare_directories(source_tree_directory, target_tree_directory)?;
+    // let's go hike
+    info!(
+        "🚶 walking source directory {:?} and create tree 🌳",
+        source_tree_directory
+    );
+    let mut report = build_site(
+        source_tree_directory,
+        target_tree_directory,
+        options,
+        site_options,
+        jobs,
+        force,
+    );
+    if !report.stale.is_empty() {
+        match clean {
+            Clean::Keep => info!(
+                "ℹ️  {} output(s) of previous builds have no source anymore, list them with --dry-run, remove them with --clean",
+                report.stale.len()
+            ),
+            Clean::List => {
+                for output in &report.stale {
+                    info!("- 🧹 would remove {:?}", target_tree_directory.join(output));
+                }
+            }
+            Clean::Remove => prune(target_tree_directory, &mut report),
+        }
+    }
+    info!("---------------------");
+    info!(
+        "📊 {} file(s) converted, {} file(s) copied, {} file(s) unchanged, {} file(s) removed, {} error(s)",
+        report.converted,
+        report.copied,
+        report.unchanged,
+        report.removed,
+        report.errors.len()
+    );
+    // summary of all failures, at the end of the build
+    if !report.is_success() {
+        error!("😢 {} error(s) during the build :", report.errors.len());
+        for e in &report.errors {
+            error!("  - {e}");
+        }
+    }
+    info!("💤 end gemini2html");
+    Ok(report.is_success())
+}
+
+/// Convert a single file, `-` is the standard input or output
+fn convert(source: &Path, target: &Path, options: &Options) -> Result<bool, Gemini2HtmlError> {
+    convert_gemini_file(source, target, options)?;
+    info!("✅ {:?} converted to {:?}", source, target);
+    Ok(true)
+}
+
+/// Check the source tree, and print the problems found
+fn check(
+    source_tree_directory: &Path,
+    options: &Options,
+    site_options: &SiteOptions,
+) -> Result<bool, Gemini2HtmlError> {
+    check_source_directory(source_tree_directory)?;
+    let report = check_site(source_tree_directory, options, site_options);
+    for e in &report.errors {
+        error!("  - {e}");
+    }
+    for broken_link in &report.broken_links {
+        error!(
+            "  - broken link in {} : {}",
+            broken_link.file.display(),
+            broken_link.url
         );
-        // TODO target tree must no be in source, infinite loop...
-        convert_tree(
-            source_tree_directory,
-            source_tree_directory,
-            target_tree_directory,
-        )?;
-        info!("---------------------");
-        info!("💤 end gemini2html");
-        Ok(())
-    } else {
-        error!("source directory {:?} not found", source_tree_directory);
-        info!("---------------------");
-        info!("💤 end gemini2html");
-        Err(Gemini2HtmlError)
     }
+    info!(
+        "📊 {} file(s) checked, {} error(s), {} broken link(s)",
+        report.checked,
+        report.errors.len(),
+        report.broken_links.len()
+    );
+    Ok(report.is_success())
+}
+
+/// Create a new capsule, with an index and a configuration file
+fn new_capsule(directory: &Path) -> Result<(), Gemini2HtmlError> {
+    // never overwrite an existing capsule
+    let is_empty = fs::read_dir(directory).map_or(true, |mut entries| entries.next().is_none());
+    if !is_empty {
+        return Err(Gemini2HtmlError::Io {
+            operation: Operation::CreateDir,
+            path: directory.to_path_buf(),
+            source: io::Error::new(io::ErrorKind::AlreadyExists, "directory is not empty"),
+        });
+    }
+    fs::create_dir_all(directory).map_err(Gemini2HtmlError::io(Operation::CreateDir, directory))?;
+    let files = [
+        ("index.gmi", include_str!("new/index.gmi")),
+        ("first-post.gmi", include_str!("new/first-post.gmi")),
+        ("gemini2html.toml", include_str!("new/gemini2html.toml")),
+        (".gemini2htmlignore", include_str!("new/.gemini2htmlignore")),
+    ];
+    for (name, content) in files {
+        let path = directory.join(name);
+        fs::write(&path, content).map_err(Gemini2HtmlError::io(Operation::Write, &path))?;
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use insta::assert_snapshot;
 
-    #[test]
-    fn test_replace_ancestor() {
-        let origin_ancestor = Path::new("./origin");
-        let target = Path::new("./target");
-        let path = Path::new("./origin/some_entry");
-        let replaced_path = replace_ancestor(origin_ancestor, target, path).unwrap();
-        assert_eq!(replaced_path, Path::new("./target/some_entry"));
-    }
-    #[test]
-    fn test_convert_tree() {
-        let source_tree_directory = Path::new("./tests");
-        let target_tree_directory = Path::new("./output_tests");
-        if target_tree_directory.is_dir() {
-            fs::remove_dir_all(target_tree_directory)
-                .expect("unable to purge old test dir ./output_tests");
-        }
-        fs::create_dir(target_tree_directory).expect("unable to create ./output_tests");
-        let _ = convert_tree(
-            source_tree_directory,
-            source_tree_directory,
-            target_tree_directory,
-        );
-        let files: Vec<&Path> = vec![
-            // ⛔ ⬇️
-            Path::new("./output_tests/gemini_file.html"),
-            Path::new("./output_tests/non_gemini_file.txt"),
-            Path::new("./output_tests/subdir/subfile.html"),
-            Path::new("./output_tests/subdir/subsubdir/subfile.html"),
-            // ⬇️  always append here, or it will break cargo insta snapshots order
-            // 🟢
-        ];
-        for file in files {
-            let content = fs::read_to_string(file).expect("unable to read file in ./output_tests");
-            assert_snapshot!(content);
-        }
-        fs::remove_dir_all(target_tree_directory)
-            .expect("unable to purge old test dir ./output_tests");
+    fn args(args: &[&str]) -> Vec<String> {
+        ["gemini2html"]
+            .iter()
+            .chain(args)
+            .map(|arg| arg.to_string())
+            .collect()
     }
+
     #[test]
     fn test_parse_args() {
         let args = [
@@ -201,8 +532,189 @@ mod tests {
             "path1".to_string(),
             "path2".to_string(),
         ];
-        let (path1, path2) = parse_args(&args).unwrap();
-        assert_eq!(path1, "path1");
-        assert_eq!(path2, "path2");
+        let parsed_args = parse_args(&args).unwrap();
+        assert_eq!(
+            parsed_args.command,
+            Command::Build {
+                source: PathBuf::from("path1"),
+                target: PathBuf::from("path2")
+            }
+        );
+    }
+    #[test]
+    fn test_parse_args_commands() {
+        assert_eq!(
+            parse_args(&args(&["watch", "capsule", "public"]))
+                .unwrap()
+                .command,
+            Command::Watch {
+                source: PathBuf::from("capsule"),
+                target: PathBuf::from("public")
+            }
+        );
+        assert_eq!(
+            parse_args(&args(&["convert", "a.gmi", "a.html"]))
+                .unwrap()
+                .command,
+            Command::Convert {
+                source: PathBuf::from("a.gmi"),
+                target: PathBuf::from("a.html")
+            }
+        );
+        assert_eq!(
+            parse_args(&args(&["convert", "a.gmi"])).unwrap().command,
+            Command::Convert {
+                source: PathBuf::from("a.gmi"),
+                target: PathBuf::from("-")
+            }
+        );
+        assert_eq!(
+            parse_args(&args(&["-"])).unwrap().command,
+            Command::Convert {
+                source: PathBuf::from("-"),
+                target: PathBuf::from("-")
+            }
+        );
+        assert_eq!(
+            parse_args(&args(&["a.gmi", "-"])).unwrap().command,
+            Command::Convert {
+                source: PathBuf::from("a.gmi"),
+                target: PathBuf::from("-")
+            }
+        );
+        assert_eq!(
+            parse_args(&args(&["check", "src"])).unwrap().command,
+            Command::Check {
+                source: PathBuf::from("src")
+            }
+        );
+        assert_eq!(
+            parse_args(&args(&["new", "capsule"])).unwrap().command,
+            Command::New {
+                directory: PathBuf::from("capsule")
+            }
+        );
+        assert_eq!(
+            parse_args(&args(&["serve", "--help"])).unwrap().command,
+            Command::Help
+        );
+        assert_eq!(
+            parse_args(&args(&["-V"])).unwrap().command,
+            Command::Version
+        );
+    }
+    #[test]
+    fn test_parse_args_options() {
+        let parsed_args = parse_args(&args(&[
+            "serve",
+            "-c",
+            "my.toml",
+            "public",
+            "--port=1965",
+            "--extension",
+            "gemini",
+            "--credits",
+            "me",
+            "--css",
+            "my.css",
+            "-l",
+            "warn",
+            "--force",
+            "--dry-run",
+            "--clean",
+            "-j",
+            "4",
+        ]))
+        .unwrap();
+        assert_eq!(
+            parsed_args,
+            Args {
+                command: Command::Serve {
+                    directory: PathBuf::from("public")
+                },
+                config_file: Some(PathBuf::from("my.toml")),
+                flags: Config {
+                    extension: Some("gemini".to_string()),
+                    credits: Some("me".to_string()),
+                    css: Some(PathBuf::from("my.css")),
+                    log_level: Some("warn".to_string()),
+                    port: Some(1965),
+                    jobs: Some(4),
+                    force: Some(true),
+                    clean: Some(true),
+                    dry_run: Some(true),
+                    ..Config::default()
+                },
+            }
+        );
+    }
+    #[test]
+    fn test_clean_from_config() {
+        let clean = |config: &str| Clean::from_config(&Config::parse(config).unwrap());
+        assert_eq!(clean(""), Clean::Keep);
+        assert_eq!(clean("clean = true"), Clean::Remove);
+        assert_eq!(clean("clean = true\ndry_run = true"), Clean::List);
+        assert_eq!(clean("clean = false\ndry_run = false"), Clean::Keep);
+        // a flag overrides the configuration file
+        let flags = Config {
+            dry_run: Some(true),
+            ..Config::default()
+        };
+        assert_eq!(
+            Clean::from_config(&flags.or(Config::parse("clean = true").unwrap())),
+            Clean::List
+        );
+    }
+    #[test]
+    fn test_parse_args_errors() {
+        assert!(parse_args(&args(&[])).is_err());
+        assert!(parse_args(&args(&["only_one_path"])).is_err());
+        assert!(parse_args(&args(&["a", "b", "--unknown"])).is_err());
+        assert!(parse_args(&args(&["a", "b", "--port"])).is_err());
+        assert!(parse_args(&args(&["a", "b", "--port", "http"])).is_err());
+        assert!(parse_args(&args(&["a", "b", "--theme", "pink"])).is_err());
+        assert!(parse_args(&args(&["a", "b", "--stylesheet", "shraed"])).is_err());
+        assert!(parse_args(&args(&["a", "b", "--jobs", "0"])).is_err());
+    }
+    #[test]
+    fn test_capsule_directory() {
+        let command = parse_args(&args(&["build", "capsule", "public"]))
+            .unwrap()
+            .command;
+        assert_eq!(command.capsule_directory(), Some(Path::new("capsule")));
+        let command = parse_args(&args(&["convert", "capsule/post.gmi", "post.html"]))
+            .unwrap()
+            .command;
+        assert_eq!(command.capsule_directory(), Some(Path::new("capsule")));
+        let command = parse_args(&args(&["watch", "capsule", "public"]))
+            .unwrap()
+            .command;
+        assert_eq!(command.capsule_directory(), Some(Path::new("capsule")));
+        let command = parse_args(&args(&["serve", "public"])).unwrap().command;
+        assert_eq!(command.capsule_directory(), None);
+        // only `--config` configures the conversion of stdin
+        for stdin in [&["convert", "-"][..], &["-"], &["-", "post.html"]] {
+            let command = parse_args(&args(stdin)).unwrap().command;
+            assert_eq!(command.capsule_directory(), None);
+        }
+    }
+    #[test]
+    fn test_log_level() {
+        assert_eq!(log_level("warn").unwrap(), LevelFilter::Warn);
+        assert_eq!(log_level("DEBUG").unwrap(), LevelFilter::Debug);
+        assert!(log_level("loud").is_err());
+    }
+    #[test]
+    fn test_new_capsule() {
+        let directory = env::temp_dir().join("gemini2html_new_capsule");
+        let _ = fs::remove_dir_all(&directory);
+        new_capsule(&directory).unwrap();
+        assert!(directory.join("index.gmi").is_file());
+        assert!(directory.join(".gemini2htmlignore").is_file());
+        let config = Config::load(&directory.join("gemini2html.toml")).unwrap();
+        assert_eq!(config.extension, Some("gmi".to_string()));
+        // never overwrite an existing capsule
+        assert!(new_capsule(&directory).is_err());
+        fs::remove_dir_all(&directory).expect("unable to purge test directory");
     }
 }
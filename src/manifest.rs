@@ -0,0 +1,529 @@
+//! Build manifest : what the previous build was made from
+//!
+//! It is written at the root of the target directory, with a stamp of every
+//! source file and a fingerprint of the options, so a build only writes the
+//! files which changed since the previous one. It also lists the outputs of
+//! the build, to find the ones whose source was removed.
+//!
+//! Paths are escaped, one per line : `\` and line breaks are written `\\`, `\n`
+//! and `\r`, and the bytes of a name which is not utf-8 are written like `\xff`.
+
+use crate::feed::{FeedContent, FeedLinks, FeedOptions};
+use crate::gemlog::GemlogOptions;
+use crate::site::SiteOptions;
+use crate::template::Template;
+use crate::{Gemini2HtmlError, Operation, Options, Result, Stylesheet};
+use log::{info, warn};
+use std::collections::{BTreeMap, BTreeSet};
+#[cfg(unix)]
+use std::ffi::OsString;
+use std::fs;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Name of the manifest, at the root of the target directory
+pub const MANIFEST_FILE_NAME: &str = ".gemini2html-manifest";
+
+/// First line of the manifest, followed by the fingerprint of the options
+const HEADER: &str = "gemini2html manifest ";
+/// Prefix of the lines of the sources, followed by their stamp and their path
+const SOURCE: &str = "source ";
+/// Prefix of the lines of the outputs, followed by their path
+const OUTPUT: &str = "output ";
+
+/// A source file, as it was built
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Stamp {
+    /// Hash of the content
+    hash: u64,
+    size: u64,
+    /// Modification time, in nanoseconds since the epoch
+    modified: u128,
+}
+
+/// Sources and outputs of the previous build and of the current one,
+/// paths are relative to the root of the capsule, or of the target directory
+#[derive(Debug, Default)]
+pub(crate) struct Manifest {
+    fingerprint: u64,
+    /// Empty if there was no previous build, or if it was made with other options
+    previous: BTreeMap<PathBuf, Stamp>,
+    current: BTreeMap<PathBuf, Stamp>,
+    /// Kept whatever the options of the previous build
+    previous_outputs: BTreeSet<PathBuf>,
+    outputs: BTreeSet<PathBuf>,
+    /// Pages show the modification time of their source, a touched file is built again
+    uses_modified: bool,
+}
+
+/// Content of a manifest file
+#[derive(Debug, Default, PartialEq)]
+struct Content {
+    fingerprint: u64,
+    sources: BTreeMap<PathBuf, Stamp>,
+    outputs: BTreeSet<PathBuf>,
+}
+
+impl Manifest {
+    /// Manifest of a build in the target directory, the previous build is only
+    /// reused if it was made by the same version of gemini2html with the same options,
+    /// and if the build is not forced
+    pub(crate) fn load(
+        target_tree_directory: &Path,
+        options: &Options,
+        site_options: &SiteOptions,
+        force: bool,
+    ) -> Manifest {
+        let fingerprint = fingerprint(options, site_options);
+        let path = target_tree_directory.join(MANIFEST_FILE_NAME);
+        let mut previous = match fs::read_to_string(&path)
+            .ok()
+            .map(|content| parse(&content))
+        {
+            Some(Some(content)) => content,
+            Some(None) => {
+                warn!("{:?} is not a valid manifest, every file is built", path);
+                Content::default()
+            }
+            None => Content::default(),
+        };
+        if !previous.sources.is_empty() && (force || previous.fingerprint != fingerprint) {
+            match force {
+                true => info!("ℹ️  forced build, every file is built"),
+                false => info!("ℹ️  options changed since the previous build, every file is built"),
+            }
+            previous.sources.clear();
+        }
+        Manifest {
+            fingerprint,
+            previous: previous.sources,
+            previous_outputs: previous.outputs,
+            uses_modified: options
+                .template
+                .as_ref()
+                .is_some_and(Template::uses_last_modified),
+            ..Manifest::default()
+        }
+    }
+
+    /// True if the previous build can be reused
+    pub(crate) fn is_incremental(&self) -> bool {
+        !self.previous.is_empty()
+    }
+
+    /// Stamp of a source file, `None` if it can't be read
+    /// the content is only hashed if the size or the modification time changed
+    pub(crate) fn stamp(&self, path: &Path, file: &Path) -> Option<Stamp> {
+        let metadata = fs::metadata(file).ok()?;
+        let size = metadata.len();
+        let modified = metadata
+            .modified()
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_nanos();
+        if let Some(previous) = self.previous.get(path)
+            && (previous.size, previous.modified) == (size, modified)
+        {
+            return Some(*previous);
+        }
+        let content = fs::read(file).ok()?;
+        Some(Stamp {
+            hash: fnv1a(&content),
+            size,
+            modified,
+        })
+    }
+
+    /// True if the source has the content of the previous build and its output still exists,
+    /// a file only touched is not built again, unless the template shows its `last_modified`
+    pub(crate) fn is_up_to_date(&self, path: &Path, stamp: Option<Stamp>, output: &Path) -> bool {
+        let unchanged = |stamp: Stamp| {
+            self.previous.get(path).is_some_and(|previous| {
+                (previous.hash, previous.size) == (stamp.hash, stamp.size)
+                    && (!self.uses_modified || previous.modified == stamp.modified)
+            })
+        };
+        stamp.is_some_and(unchanged) && output.is_file()
+    }
+
+    /// Record a source of the current build
+    pub(crate) fn record(&mut self, path: &Path, stamp: Option<Stamp>) {
+        if let Some(stamp) = stamp {
+            self.current.insert(path.to_path_buf(), stamp);
+        }
+    }
+
+    /// Record an output of the current build, whether it was written or not
+    pub(crate) fn record_output(&mut self, path: &Path) {
+        self.outputs.insert(path.to_path_buf());
+    }
+
+    /// Outputs of a previous build which are not outputs of the current one,
+    /// and are still in the target directory
+    pub(crate) fn stale_outputs(&self, target_tree_directory: &Path) -> Vec<PathBuf> {
+        self.previous_outputs
+            .difference(&self.outputs)
+            .filter(|path| target_tree_directory.join(path).is_file())
+            .cloned()
+            .collect()
+    }
+
+    /// True if a source matching `filter` was added, changed or removed since the previous build,
+    /// or failed to build
+    pub(crate) fn has_changed(&self, filter: impl Fn(&Path) -> bool) -> bool {
+        let changed = |(path, stamp): (&PathBuf, &Stamp)| {
+            filter(path)
+                && self
+                    .previous
+                    .get(path)
+                    .is_none_or(|previous| previous.hash != stamp.hash)
+        };
+        let removed = |path: &PathBuf| filter(path) && !self.current.contains_key(path);
+        !self.is_incremental()
+            || self.current.iter().any(changed)
+            || self.previous.keys().any(removed)
+    }
+
+    /// Write the sources and the outputs of the current build, for the next one
+    /// stale outputs are kept until they are removed
+    pub(crate) fn write(&self, target_tree_directory: &Path) -> Result<()> {
+        let mut content = format!("{HEADER}{:016x}\n", self.fingerprint);
+        for (path, stamp) in &self.current {
+            // the path is last, it may contain spaces
+            content.push_str(&format!(
+                "{SOURCE}{:016x} {} {} {}\n",
+                stamp.hash,
+                stamp.size,
+                stamp.modified,
+                escape_path(path)
+            ));
+        }
+        let stale_outputs = self.stale_outputs(target_tree_directory);
+        let mut outputs: Vec<_> = self.outputs.iter().chain(&stale_outputs).collect();
+        outputs.sort();
+        for path in outputs {
+            content.push_str(&format!("{OUTPUT}{}\n", escape_path(path)));
+        }
+        let path = target_tree_directory.join(MANIFEST_FILE_NAME);
+        fs::write(&path, content).map_err(Gemini2HtmlError::io(Operation::Write, &path))
+    }
+}
+
+/// Content of a manifest file, `None` if its header is invalid
+/// an invalid line is skipped with a warning : its source is built again,
+/// and its output is not found stale
+fn parse(manifest: &str) -> Option<Content> {
+    let mut lines = manifest.lines();
+    let header = lines.next()?.strip_prefix(HEADER)?;
+    let mut content = Content {
+        fingerprint: u64::from_str_radix(header, 16).ok()?,
+        ..Content::default()
+    };
+    for (number, line) in lines.enumerate() {
+        if parse_line(line, &mut content).is_none() {
+            warn!("invalid line {} of the manifest is skipped", number + 2);
+        }
+    }
+    Some(content)
+}
+
+/// Add a source or an output line to the content of a manifest, `None` if it is invalid
+fn parse_line(line: &str, content: &mut Content) -> Option<()> {
+    if let Some(output) = line.strip_prefix(OUTPUT) {
+        content.outputs.insert(unescape_path(output)?);
+        return Some(());
+    }
+    let mut fields = line.strip_prefix(SOURCE)?.splitn(4, ' ');
+    let stamp = Stamp {
+        hash: u64::from_str_radix(fields.next()?, 16).ok()?,
+        size: fields.next()?.parse().ok()?,
+        modified: fields.next()?.parse().ok()?,
+    };
+    content
+        .sources
+        .insert(unescape_path(fields.next()?)?, stamp);
+    Some(())
+}
+
+/// A path on a single line of the manifest, whatever its bytes
+fn escape_path(path: &Path) -> String {
+    let mut escaped = String::new();
+    for chunk in path.as_os_str().as_encoded_bytes().utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\\' => escaped.push_str("\\\\"),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                c => escaped.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            escaped.push_str(&format!("\\x{byte:02x}"));
+        }
+    }
+    escaped
+}
+
+/// A path written by [`escape_path`], `None` if it is invalid or can't be a path here
+fn unescape_path(escaped: &str) -> Option<PathBuf> {
+    let mut bytes = Vec::new();
+    let mut chars = escaped.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            continue;
+        }
+        match chars.next()? {
+            '\\' => bytes.push(b'\\'),
+            'n' => bytes.push(b'\n'),
+            'r' => bytes.push(b'\r'),
+            'x' => {
+                let hex: String = chars.by_ref().take(2).collect();
+                bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+            }
+            _ => return None,
+        }
+    }
+    match String::from_utf8(bytes) {
+        Ok(path) => Some(PathBuf::from(path)),
+        #[cfg(unix)]
+        Err(e) => Some(PathBuf::from(OsString::from_vec(e.into_bytes()))),
+        // the manifest of another platform
+        #[cfg(not(unix))]
+        Err(_) => None,
+    }
+}
+
+/// Everything a page depends on besides its source : the version of gemini2html,
+/// its features, and the options with the css and the template,
+/// with the options of the generated gemlog index and feeds
+/// each field is named and its value prefixed with its length, so no two builds
+/// share a fingerprint by accident
+fn fingerprint(options: &Options, site_options: &SiteOptions) -> u64 {
+    // destructured, a new option can't be forgotten here
+    let Options {
+        site_name,
+        base_url,
+        language,
+        author,
+        credits,
+        theme,
+        css,
+        extra_css,
+        stylesheet,
+        gemini_extension,
+        image_extensions,
+        template,
+        feed_links: FeedLinks { atom, rss },
+    } = options;
+    let optional = |value: Option<&str>| match value {
+        Some(value) => format!("some {value}"),
+        None => "none".to_string(),
+    };
+    let stylesheet = match stylesheet {
+        Stylesheet::Inline => "inline".to_string(),
+        Stylesheet::Shared => "shared".to_string(),
+        Stylesheet::External(url) => format!("external {url}"),
+    };
+    let mut fields = vec![
+        ("version", env!("CARGO_PKG_VERSION").to_string()),
+        ("highlight", cfg!(feature = "highlight").to_string()),
+        ("site_name", optional(site_name.as_deref())),
+        ("base_url", optional(base_url.as_deref())),
+        ("language", optional(language.as_deref())),
+        ("author", optional(author.as_deref())),
+        ("credits", credits.clone()),
+        ("theme", theme.name().to_string()),
+        ("css", optional(css.as_deref())),
+        ("extra_css", optional(extra_css.as_deref())),
+        ("stylesheet", stylesheet),
+        ("gemini_extension", gemini_extension.clone()),
+        ("image_extensions", image_extensions.join(" ")),
+        (
+            "template",
+            optional(template.as_ref().map(Template::source)),
+        ),
+        ("atom_link", atom.to_string()),
+        ("rss_link", rss.to_string()),
+    ];
+    if let Some(GemlogOptions { posts, title }) = &site_options.gemlog {
+        fields.push(("gemlog_posts", posts.display().to_string()));
+        fields.push(("gemlog_title", title.clone()));
+    }
+    if let Some(FeedOptions {
+        source,
+        atom,
+        rss,
+        limit,
+        content,
+    }) = &site_options.feed
+    {
+        let content = match content {
+            FeedContent::Full => "full",
+            FeedContent::Summary => "summary",
+        };
+        fields.push(("feed_source", source.display().to_string()));
+        fields.push(("feed_atom", atom.to_string()));
+        fields.push(("feed_rss", rss.to_string()));
+        fields.push((
+            "feed_limit",
+            optional(limit.map(|limit| limit.to_string()).as_deref()),
+        ));
+        fields.push(("feed_content", content.to_string()));
+    }
+    let build: String = fields
+        .iter()
+        .map(|(name, value)| format!("{name} {} {value}\n", value.len()))
+        .collect();
+    fnv1a(build.as_bytes())
+}
+
+/// 64 bits FNV-1a hash, stable from one build to the next unlike the hasher of `std`
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_tree::TestTree;
+
+    #[test]
+    fn test_fnv1a() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = Manifest {
+            fingerprint: 42,
+            current: BTreeMap::from([(
+                PathBuf::from("posts/my post.gmi"),
+                Stamp {
+                    hash: 7,
+                    size: 12,
+                    modified: 1_790_000_000_000_000_000,
+                },
+            )]),
+            outputs: BTreeSet::from([PathBuf::from("posts/my post.html")]),
+            ..Manifest::default()
+        };
+        let directory = TestTree::new("manifest");
+        manifest.write(&directory).unwrap();
+        let content = fs::read_to_string(directory.join(MANIFEST_FILE_NAME)).unwrap();
+        assert_eq!(
+            content,
+            "gemini2html manifest 000000000000002a\nsource 0000000000000007 12 1790000000000000000 posts/my post.gmi\noutput posts/my post.html\n"
+        );
+        assert_eq!(
+            parse(&content),
+            Some(Content {
+                fingerprint: 42,
+                sources: manifest.current,
+                outputs: manifest.outputs,
+            })
+        );
+        assert_eq!(parse("not a manifest"), None);
+        // an invalid line doesn't discard the others
+        assert_eq!(
+            parse("gemini2html manifest 000000000000002a\nsource 7 x posts/a.gmi\noutput a.html\n"),
+            Some(Content {
+                fingerprint: 42,
+                outputs: BTreeSet::from([PathBuf::from("a.html")]),
+                ..Content::default()
+            })
+        );
+    }
+    #[test]
+    fn test_escape_path() {
+        let paths = [
+            "posts/my post.gmi",
+            "line\nbreak.gmi",
+            "back\\slash\r.gmi",
+            "été.gmi",
+        ];
+        for path in paths {
+            let escaped = escape_path(Path::new(path));
+            assert!(!escaped.contains(['\n', '\r']));
+            assert_eq!(unescape_path(&escaped), Some(PathBuf::from(path)));
+        }
+        assert_eq!(
+            escape_path(Path::new("line\nbreak.gmi")),
+            "line\\nbreak.gmi"
+        );
+        assert_eq!(unescape_path("trailing\\"), None);
+        assert_eq!(unescape_path("bad\\xzz"), None);
+    }
+    #[cfg(unix)]
+    #[test]
+    fn test_escape_non_utf8_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        let path = Path::new(OsStr::from_bytes(b"latin\xe9.gmi"));
+        assert_eq!(escape_path(path), "latin\\xe9.gmi");
+        assert_eq!(unescape_path(&escape_path(path)).as_deref(), Some(path));
+    }
+    #[test]
+    fn test_fingerprint() {
+        let options = Options::default();
+        let site_options = SiteOptions::default();
+        let fingerprint_of = |options: &Options| fingerprint(options, &site_options);
+        assert_eq!(fingerprint_of(&options), fingerprint_of(&options.clone()));
+        // an empty css is not the built-in one
+        let empty_css = Options {
+            css: Some(String::new()),
+            ..options.clone()
+        };
+        assert_ne!(fingerprint_of(&empty_css), fingerprint_of(&options));
+        let template = |template: &str| Options {
+            template: Some(Template::parse(template).unwrap()),
+            ..options.clone()
+        };
+        assert_ne!(
+            fingerprint_of(&template("{{ content }}")),
+            fingerprint_of(&template("<main>{{ content }}</main>"))
+        );
+        let gemlog = SiteOptions {
+            gemlog: Some(GemlogOptions::default()),
+            ..SiteOptions::default()
+        };
+        assert_ne!(fingerprint(&options, &gemlog), fingerprint_of(&options));
+        // include, exclude and drafts select the files, they don't change the pages
+        let drafts = SiteOptions {
+            drafts: true,
+            ..SiteOptions::default()
+        };
+        assert_eq!(fingerprint(&options, &drafts), fingerprint_of(&options));
+    }
+    #[test]
+    fn test_touched_file() {
+        let directory = TestTree::new("manifest_touched");
+        fs::write(directory.join("page.gmi"), "# page\n").unwrap();
+        fs::write(directory.join("page.html"), "").unwrap();
+        let stamp = |modified: u128| Stamp {
+            hash: fnv1a(b"# page\n"),
+            size: 7,
+            modified,
+        };
+        let manifest = |template: Option<&str>| Manifest {
+            previous: BTreeMap::from([(PathBuf::from("page.gmi"), stamp(1))]),
+            uses_modified: template
+                .map(|template| Template::parse(template).unwrap())
+                .is_some_and(|template| template.uses_last_modified()),
+            ..Manifest::default()
+        };
+        let output = directory.join("page.html");
+        let is_up_to_date = |manifest: Manifest, modified: u128| {
+            manifest.is_up_to_date(Path::new("page.gmi"), Some(stamp(modified)), &output)
+        };
+        assert!(is_up_to_date(manifest(None), 1));
+        assert!(is_up_to_date(manifest(None), 2));
+        assert!(is_up_to_date(manifest(Some("{{ last_modified }}")), 1));
+        assert!(!is_up_to_date(manifest(Some("{{ last_modified }}")), 2));
+    }
+}
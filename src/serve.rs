@@ -0,0 +1,210 @@
+//! A tiny http server, to preview a built site locally
+//!
+//! Only `GET` and `HEAD` requests for static files are handled, each connection on its thread.
+//! Not meant to be exposed on the internet, use a real web server for that.
+
+use crate::site::percent_decode;
+use crate::{Gemini2HtmlError, Operation, Result};
+use log::{debug, info, warn};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// Time given to a client to send its request, browsers open connections in advance
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serve `directory` on `http://127.0.0.1:<port>`, never returns unless the port can't be used
+pub fn serve(directory: &Path, port: u16) -> Result<()> {
+    let address = format!("127.0.0.1:{port}");
+    let listener = TcpListener::bind(&address)
+        .map_err(Gemini2HtmlError::io(Operation::Listen, Path::new(&address)))?;
+    info!("🌍 serving {:?} on http://{address}/", directory);
+    accept_connections(directory, &listener);
+    Ok(())
+}
+
+/// Answer the connections of a listener, an idle one doesn't block the others
+fn accept_connections(directory: &Path, listener: &TcpListener) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let directory = directory.to_path_buf();
+                thread::spawn(move || {
+                    if let Err(e) = handle_request(&directory, stream) {
+                        warn!("unable to answer request : {e}");
+                    }
+                });
+            }
+            Err(e) => warn!("connection failed : {e}"),
+        }
+    }
+}
+
+/// Answer a single request
+fn handle_request(directory: &Path, mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    debug!("request : {}", request_line.trim_end());
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or_default(), parts.next());
+    let response = match (method, target) {
+        ("GET" | "HEAD", Some(target)) => match resolve_path(directory, target) {
+            Some(path) => match fs::read(&path) {
+                Ok(body) => {
+                    info!("- 200 {target}");
+                    Response::new(200, "OK", content_type(&path), body)
+                }
+                Err(_) => {
+                    info!("- 404 {target}");
+                    Response::new(404, "Not Found", "text/plain", b"not found\n".to_vec())
+                }
+            },
+            None => Response::new(400, "Bad Request", "text/plain", b"bad request\n".to_vec()),
+        },
+        _ => Response::new(
+            405,
+            "Method Not Allowed",
+            "text/plain",
+            b"method not allowed\n".to_vec(),
+        ),
+    };
+    stream.write_all(&response.header())?;
+    if method != "HEAD" {
+        stream.write_all(&response.body)?;
+    }
+    Ok(())
+}
+
+/// A http response
+struct Response {
+    status: u16,
+    reason: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn new(status: u16, reason: &'static str, content_type: &'static str, body: Vec<u8>) -> Self {
+        Response {
+            status,
+            reason,
+            content_type,
+            body,
+        }
+    }
+
+    fn header(&self) -> Vec<u8> {
+        format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.status,
+            self.reason,
+            self.content_type,
+            self.body.len()
+        )
+        .into_bytes()
+    }
+}
+
+/// File requested by a request target, `None` if the target tries to escape the directory
+/// directories are served with their `index.html`
+fn resolve_path(directory: &Path, target: &str) -> Option<PathBuf> {
+    let path = target.split(['?', '#']).next().unwrap_or_default();
+    let path = percent_decode(path)?;
+    let relative_path = Path::new(path.trim_start_matches('/'));
+    // only plain names, no `..` or absolute paths
+    if !relative_path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+    let path = directory.join(relative_path);
+    match path.is_dir() {
+        true => Some(path.join("index.html")),
+        false => Some(path),
+    }
+}
+
+/// Content type from the file extension
+fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("xml") => "application/xml; charset=utf-8",
+        Some("gmi") => "text/gemini; charset=utf-8",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("svg") => "image/svg+xml",
+        Some("bmp") => "image/bmp",
+        Some("tiff") => "image/tiff",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_resolve_path() {
+        let directory = Path::new("./tests");
+        assert_eq!(
+            resolve_path(directory, "/gemini_file.gmi?query"),
+            Some(PathBuf::from("./tests/gemini_file.gmi"))
+        );
+        assert_eq!(
+            resolve_path(directory, "/subdir/"),
+            Some(PathBuf::from("./tests/subdir/index.html"))
+        );
+        assert_eq!(
+            resolve_path(directory, "/"),
+            Some(PathBuf::from("./tests/index.html"))
+        );
+        assert_eq!(
+            resolve_path(directory, "/a%20file.txt"),
+            Some(PathBuf::from("./tests/a file.txt"))
+        );
+        assert_eq!(resolve_path(directory, "/../Cargo.toml"), None);
+        assert_eq!(resolve_path(directory, "/%2e%2e/Cargo.toml"), None);
+        assert_eq!(resolve_path(directory, "/%zz"), None);
+    }
+    #[test]
+    fn test_content_type() {
+        assert_eq!(
+            content_type(Path::new("a/index.HTML")),
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(content_type(Path::new("a.avif")), "image/avif");
+        assert_eq!(content_type(Path::new("a")), "application/octet-stream");
+    }
+    #[test]
+    fn test_idle_connection_does_not_block() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || accept_connections(Path::new("./tests"), &listener));
+        // a preconnect of a browser, which sends nothing
+        let _idle = TcpStream::connect(address).unwrap();
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        stream
+            .write_all(b"GET /non_gemini_file.txt HTTP/1.1\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+}
@@ -0,0 +1,1314 @@
+//! Site build : convert a whole tree of gemini files to html
+//!
+//! A failure on a file does not stop the build, every error is collected
+//! in the [`BuildReport`] so all broken files can be fixed at once.
+
+use crate::config::CONFIG_FILE_NAME;
+use crate::feed::{ATOM_FILE_NAME, FeedOptions, RSS_FILE_NAME, write_feeds};
+use crate::gemlog::{GemlogOptions, is_generated_index, write_index};
+use crate::ignore::{IGNORE_FILE_NAME, SourceFilter};
+use crate::manifest::{Manifest, Stamp};
+use crate::{
+    Gemini2HtmlError, Operation, Options, Result, STYLESHEET_FILE_NAME, Stylesheet, convert_page,
+    parse, read_from_file, stylesheet,
+};
+use log::info;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// What a site build does besides converting its pages, see [`Options`] for the pages
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SiteOptions {
+    /// Feeds of a gemlog page, they need the `base_url`
+    pub feed: Option<FeedOptions>,
+    /// Gemlog index generated by the build
+    pub gemlog: Option<GemlogOptions>,
+    /// Globs of the files to build, relative to the capsule, every file if empty
+    pub include: Vec<String>,
+    /// Globs of the files not to build, added to the `.gemini2htmlignore` of the capsule
+    pub exclude: Vec<String>,
+    /// Build drafts too, like `post.draft.gmi`
+    pub drafts: bool,
+}
+
+/// Summary of a site build
+#[derive(Debug, Default)]
+pub struct BuildReport {
+    /// Number of gemini files converted to html
+    pub converted: usize,
+    /// Number of other files copied
+    pub copied: usize,
+    /// Number of files left as they were, unchanged since the previous build
+    pub unchanged: usize,
+    /// Outputs of a previous build whose source was removed, relative to the target directory
+    /// they are only removed by [`prune`]
+    pub stale: Vec<PathBuf>,
+    /// Number of stale outputs removed
+    pub removed: usize,
+    /// Every failure, the build went on after them
+    pub errors: Vec<Gemini2HtmlError>,
+}
+
+impl BuildReport {
+    /// True if the whole tree was built without error
+    pub fn is_success(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Summary of a site check
+#[derive(Debug, Default)]
+pub struct CheckReport {
+    /// Number of gemini files checked
+    pub checked: usize,
+    /// Gemini files which can't be read
+    pub errors: Vec<Gemini2HtmlError>,
+    /// Links to local files which don't exist
+    pub broken_links: Vec<BrokenLink>,
+}
+
+impl CheckReport {
+    /// True if no problem was found
+    pub fn is_success(&self) -> bool {
+        self.errors.is_empty() && self.broken_links.is_empty()
+    }
+}
+
+/// A link to a local file which doesn't exist
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrokenLink {
+    /// Gemini file containing the link
+    pub file: PathBuf,
+    pub url: String,
+}
+
+/// Check every gemini file of the source tree, without writing anything :
+/// files must be readable utf-8, and links to local files must point to existing files
+pub fn check_site(
+    source_tree_directory: &Path,
+    options: &Options,
+    site_options: &SiteOptions,
+) -> CheckReport {
+    let mut report = CheckReport::default();
+    let filter = match SourceFilter::load(source_tree_directory, site_options) {
+        Ok(filter) => filter,
+        Err(e) => {
+            report.errors.push(e);
+            return report;
+        }
+    };
+    let mut gemini_files = Vec::new();
+    find_gemini_files(
+        source_tree_directory,
+        source_tree_directory,
+        &filter,
+        &options.gemini_extension,
+        &mut gemini_files,
+        &mut report.errors,
+    );
+    for gemini_file in gemini_files {
+        info!("- 🔎 check {:?}", gemini_file);
+        let content = match read_from_file(&gemini_file) {
+            Ok(content) => content,
+            Err(e) => {
+                report.errors.push(e);
+                continue;
+            }
+        };
+        report.checked += 1;
+        let directory = gemini_file.parent().unwrap_or(source_tree_directory);
+        for link in parse(&content).links() {
+            if let Some(local_path) = local_link_path(&link.url)
+                && !resolve_local_link(source_tree_directory, directory, local_path).exists()
+            {
+                report.broken_links.push(BrokenLink {
+                    file: gemini_file.clone(),
+                    url: link.url.clone(),
+                });
+            }
+        }
+    }
+    report
+}
+
+/// Recursively find gemini files in a directory, except the ones the filter excludes
+fn find_gemini_files(
+    root: &Path,
+    directory: &Path,
+    filter: &SourceFilter,
+    gemini_extension: &str,
+    gemini_files: &mut Vec<PathBuf>,
+    errors: &mut Vec<Gemini2HtmlError>,
+) {
+    let tree = match fs::read_dir(directory) {
+        Ok(tree) => tree,
+        Err(e) => {
+            errors.push(Gemini2HtmlError::io(Operation::ReadDir, directory)(e));
+            return;
+        }
+    };
+    for direntry in tree {
+        match direntry {
+            Ok(direntry) => {
+                let path = direntry.path();
+                let source_path = path.strip_prefix(root).unwrap_or(&path);
+                if filter.is_excluded(source_path, path.is_dir()) {
+                    continue;
+                } else if path.is_dir() {
+                    find_gemini_files(root, &path, filter, gemini_extension, gemini_files, errors);
+                } else if path.extension() == Some(OsStr::new(gemini_extension)) {
+                    gemini_files.push(path);
+                }
+            }
+            Err(e) => errors.push(Gemini2HtmlError::io(Operation::ReadDir, directory)(e)),
+        }
+    }
+}
+
+/// Path part of a link to a local file, `None` for links with a scheme (`https:`, `gemini:`...)
+pub(crate) fn local_link_path(url: &str) -> Option<&str> {
+    let has_scheme = url.split_once(':').is_some_and(|(scheme, _)| {
+        !scheme.is_empty()
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    });
+    if has_scheme || url.starts_with("//") {
+        return None;
+    }
+    // query and fragment are not part of the file
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    (!path.is_empty()).then_some(path)
+}
+
+/// Path of a local link, absolute links start at the root of the capsule
+/// `%xx` sequences are decoded, a link which is not valid percent-encoding is kept as is
+pub(crate) fn resolve_local_link(root: &Path, directory: &Path, link_path: &str) -> PathBuf {
+    let link_path = percent_decode(link_path).unwrap_or_else(|| link_path.to_string());
+    match link_path.strip_prefix('/') {
+        Some(from_root) => root.join(from_root),
+        None => directory.join(&link_path),
+    }
+}
+
+/// Encode a path segment for an url, every byte but the unreserved and safe
+/// punctuation ones becomes a `%xx` sequence
+pub(crate) fn percent_encode(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+/// Decode `%xx` sequences of an url path
+pub(crate) fn percent_decode(path: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut chars = path.bytes();
+    while let Some(byte) = chars.next() {
+        if byte == b'%' {
+            let high = (chars.next()? as char).to_digit(16)?;
+            let low = (chars.next()? as char).to_digit(16)?;
+            bytes.push((high * 16 + low) as u8);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Convert the source tree to the target directory
+/// the target directory must exist, it may be inside the source directory
+/// but not contain it
+/// only the files which changed since the previous build are written,
+/// with the pages depending on them, see [`crate::MANIFEST_FILE_NAME`]
+/// files are built on `jobs` threads, see [`default_jobs`]
+/// a `force`d build writes every file
+pub fn build_site(
+    source_tree_directory: &Path,
+    target_tree_directory: &Path,
+    options: &Options,
+    site_options: &SiteOptions,
+    jobs: usize,
+    force: bool,
+) -> BuildReport {
+    match SourceFilter::load(source_tree_directory, site_options) {
+        Ok(filter) => build_filtered_site(
+            source_tree_directory,
+            target_tree_directory,
+            options,
+            site_options,
+            &filter,
+            jobs,
+            force,
+        ),
+        Err(e) => BuildReport {
+            errors: vec![e],
+            ..BuildReport::default()
+        },
+    }
+}
+
+/// Convert the source tree as [`build_site`], with the rules of an already loaded filter
+pub(crate) fn build_filtered_site(
+    source_tree_directory: &Path,
+    target_tree_directory: &Path,
+    options: &Options,
+    site_options: &SiteOptions,
+    filter: &SourceFilter,
+    jobs: usize,
+    force: bool,
+) -> BuildReport {
+    let mut report = BuildReport::default();
+    let excluded = match nested_target(source_tree_directory, target_tree_directory) {
+        Ok(excluded) => excluded,
+        Err(e) => {
+            report.errors.push(e);
+            return report;
+        }
+    };
+    if let Some(excluded) = &excluded {
+        info!(
+            "ℹ️  target directory {:?} is inside the source, it won't be walked",
+            excluded
+        );
+    }
+    // a hand-written index is built as any page, and never replaced
+    let gemlog = site_options.gemlog.as_ref().filter(|gemlog| {
+        let is_generated = is_generated_index(source_tree_directory, gemlog);
+        if !is_generated {
+            report.errors.push(Gemini2HtmlError::Gemlog {
+                path: source_tree_directory.join(gemlog.index()),
+                message: "not a generated index, remove it or disable the gemlog".to_string(),
+            });
+        }
+        is_generated
+    });
+    let errors_before_walk = report.errors.len();
+    // the tree is walked first, then its files are built in parallel
+    let mut files = Vec::new();
+    walk_tree(
+        source_tree_directory,
+        source_tree_directory,
+        target_tree_directory,
+        excluded.as_deref(),
+        filter,
+        &mut files,
+        &mut report.errors,
+    );
+    // outputs of a directory which could not be read would look stale
+    let walk_failed = report.errors.len() > errors_before_walk;
+    // the generated index is rendered with the gemlog, not as a page
+    if let Some(gemlog) = gemlog {
+        let index = source_tree_directory.join(gemlog.index());
+        files.retain(|file| *file != index);
+    }
+    let mut manifest = Manifest::load(target_tree_directory, options, site_options, force);
+    build_files(
+        source_tree_directory,
+        target_tree_directory,
+        &files,
+        options,
+        jobs,
+        &mut manifest,
+        &mut report,
+    );
+    let is_missing = |path: &Path| !target_tree_directory.join(path).is_file();
+    // generated outputs belong to the build, even when they are not written again
+    let generated = [
+        (options.stylesheet == Stylesheet::Shared).then(|| PathBuf::from(STYLESHEET_FILE_NAME)),
+        gemlog.map(|gemlog| gemlog.index().with_extension("html")),
+        site_options
+            .feed
+            .as_ref()
+            .filter(|feed| feed.atom)
+            .map(|_| PathBuf::from(ATOM_FILE_NAME)),
+        site_options
+            .feed
+            .as_ref()
+            .filter(|feed| feed.rss)
+            .map(|_| PathBuf::from(RSS_FILE_NAME)),
+    ];
+    for output in generated.iter().flatten() {
+        manifest.record_output(output);
+    }
+    // written last, it replaces a `style.css` copied from the capsule
+    if options.stylesheet == Stylesheet::Shared
+        && (manifest.has_changed(|path| path == Path::new(STYLESHEET_FILE_NAME))
+            || is_missing(Path::new(STYLESHEET_FILE_NAME)))
+    {
+        let path = target_tree_directory.join(STYLESHEET_FILE_NAME);
+        info!("- 🎨 write shared stylesheet {:?}", path);
+        if let Err(e) = fs::write(&path, stylesheet(options))
+            .map_err(Gemini2HtmlError::io(Operation::Write, &path))
+        {
+            report.errors.push(e);
+        }
+    }
+    // feeds may embed any local page
+    let gemini_extension = OsStr::new(&options.gemini_extension);
+    let feeds_outdated = site_options.feed.as_ref().is_some_and(|feed| {
+        manifest.has_changed(|path| path.extension() == Some(gemini_extension))
+            || [(feed.atom, ATOM_FILE_NAME), (feed.rss, RSS_FILE_NAME)]
+                .iter()
+                .any(|(enabled, file_name)| *enabled && is_missing(Path::new(file_name)))
+    });
+    // the generated index may be the page of the feeds
+    let outdated_index = gemlog.filter(|gemlog| {
+        feeds_outdated
+            || manifest.has_changed(|path| path.starts_with(&gemlog.posts))
+            || !source_tree_directory.join(gemlog.index()).is_file()
+            || is_missing(&gemlog.index().with_extension("html"))
+    });
+    let index = outdated_index.and_then(|gemlog| {
+        write_index(
+            source_tree_directory,
+            target_tree_directory,
+            gemlog,
+            filter,
+            options,
+            &mut report.errors,
+        )
+        .map(|gemtext| (gemlog.index(), gemtext))
+    });
+    if let Some(feed) = &site_options.feed
+        && feeds_outdated
+    {
+        write_feeds(
+            source_tree_directory,
+            target_tree_directory,
+            feed,
+            index
+                .as_ref()
+                .map(|(path, gemtext)| (path.as_path(), gemtext.as_str())),
+            filter,
+            options,
+            &mut report.errors,
+        );
+    }
+    if walk_failed {
+        info!("ℹ️  the source tree was not fully read, stale outputs are not looked for");
+    } else {
+        report.stale = manifest.stale_outputs(target_tree_directory);
+    }
+    if let Err(e) = manifest.write(target_tree_directory) {
+        report.errors.push(e);
+    }
+    report
+}
+
+/// Remove the stale outputs of a build, and the directories they leave empty
+pub fn prune(target_tree_directory: &Path, report: &mut BuildReport) {
+    for output in &report.stale {
+        let path = target_tree_directory.join(output);
+        info!("- 🧹 remove {:?}", path);
+        if let Err(e) =
+            fs::remove_file(&path).map_err(Gemini2HtmlError::io(Operation::Remove, &path))
+        {
+            report.errors.push(e);
+            continue;
+        }
+        report.removed += 1;
+        for directory in output.ancestors().skip(1) {
+            let directory = target_tree_directory.join(directory);
+            let is_empty = fs::read_dir(&directory).is_ok_and(|mut tree| tree.next().is_none());
+            if directory == target_tree_directory
+                || !is_empty
+                || fs::remove_dir(&directory).is_err()
+            {
+                break;
+            }
+            info!("- 🧹 remove empty directory {:?}", directory);
+        }
+    }
+}
+
+/// Number of threads of a build when it is not set : one per cpu
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map_or(1, usize::from)
+}
+
+/// Canonical path of the target directory if it is inside the source directory,
+/// the walk must skip it or it would convert its own output forever
+/// a target containing the source (or being the source) is refused
+pub(crate) fn nested_target(
+    source_tree_directory: &Path,
+    target_tree_directory: &Path,
+) -> Result<Option<PathBuf>> {
+    let source = fs::canonicalize(source_tree_directory).map_err(Gemini2HtmlError::io(
+        Operation::ReadDir,
+        source_tree_directory,
+    ))?;
+    let target = fs::canonicalize(target_tree_directory).map_err(Gemini2HtmlError::io(
+        Operation::ReadDir,
+        target_tree_directory,
+    ))?;
+    if source.starts_with(&target) {
+        Err(Gemini2HtmlError::NestedDirectories {
+            source_directory: source_tree_directory.to_path_buf(),
+            target_directory: target_tree_directory.to_path_buf(),
+        })
+    } else if target.starts_with(&source) {
+        Ok(Some(target))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Walks through a tree directory, recreate arborescence, and list the files to convert or copy
+/// it's a recursive function, but we always need the original ancestor (it's ugly, I know...)
+/// entries are sorted, so the files come in the same order at each build
+fn walk_tree(
+    // TODO find a way to retrieve ancestor
+    // with `ancestors()` ? or `components()` ?
+    // see https://doc.rust-lang.org/std/path/struct.Path.html#method.ancestors
+    ancestor: &Path,
+    source_tree_directory: &Path,
+    target_tree_directory: &Path,
+    // canonical target directory, when it is inside the source
+    excluded: Option<&Path>,
+    filter: &SourceFilter,
+    files: &mut Vec<PathBuf>,
+    errors: &mut Vec<Gemini2HtmlError>,
+) {
+    let tree = match fs::read_dir(source_tree_directory) {
+        Ok(tree) => tree,
+        Err(e) => {
+            errors.push(Gemini2HtmlError::io(
+                Operation::ReadDir,
+                source_tree_directory,
+            )(e));
+            return;
+        }
+    };
+    let mut direntries = Vec::new();
+    for direntry in tree {
+        match direntry {
+            // we only need path
+            Ok(direntry) => direntries.push(direntry.path()),
+            Err(e) => errors.push(Gemini2HtmlError::io(
+                Operation::ReadDir,
+                source_tree_directory,
+            )(e)),
+        }
+    }
+    direntries.sort();
+    // start looping on dir entries
+    for direntry in direntries {
+        let source_path = direntry.strip_prefix(ancestor).unwrap_or(&direntry);
+        // never walk our own output
+        if direntry.is_dir()
+            && let Some(excluded) = excluded
+            && fs::canonicalize(&direntry).is_ok_and(|directory| directory == excluded)
+        {
+            info!("- ⏭️ skip target directory {:?}", direntry);
+        } else if filter.is_excluded(source_path, direntry.is_dir()) {
+            info!("- 🙈 ignore {:?}", direntry);
+        // recreate directory tree
+        } else if direntry.is_dir() {
+            info!("- 🗃️ found directory {:?}", direntry);
+            match create_target_directory(ancestor, target_tree_directory, &direntry) {
+                Ok(()) => walk_tree(
+                    ancestor,
+                    &direntry,
+                    target_tree_directory,
+                    excluded,
+                    filter,
+                    files,
+                    errors,
+                ),
+                // no need to walk a directory we can't write to
+                Err(e) => errors.push(e),
+            }
+        } else if [CONFIG_FILE_NAME, IGNORE_FILE_NAME]
+            .iter()
+            .any(|file_name| direntry == ancestor.join(file_name))
+        {
+            // the configuration of the capsule is not published
+            info!("- ⚙️ skip configuration file {:?}", direntry);
+        } else {
+            info!("- 🗒 found file {:?}", direntry);
+            files.push(direntry);
+        }
+    }
+}
+
+/// Recreate a source directory in the target tree
+fn create_target_directory(
+    ancestor: &Path,
+    target_tree_directory: &Path,
+    directory: &Path,
+) -> Result<()> {
+    let target_directory = replace_ancestor(ancestor, target_tree_directory, directory)?;
+    if !target_directory.is_dir() {
+        info!("- 🦢 need to create {:?}", target_directory);
+        fs::create_dir(&target_directory).map_err(Gemini2HtmlError::io(
+            Operation::CreateDir,
+            &target_directory,
+        ))?;
+    }
+    Ok(())
+}
+
+/// What a build did with a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Built {
+    Converted,
+    Copied,
+    /// Left as it was, unchanged since the previous build
+    Unchanged,
+}
+
+/// Path of the output of a source file, relative to the target directory
+fn output_path(source_path: &Path, options: &Options) -> PathBuf {
+    let mut output = source_path.to_path_buf();
+    if source_path.extension() == Some(OsStr::new(&options.gemini_extension)) {
+        // we need to change file extension
+        let _ = output.set_extension("html");
+    }
+    output
+}
+
+/// Build the files on `jobs` threads, each one takes the next file until none is left
+/// results are counted in the order of the files, whatever thread built them
+fn build_files(
+    ancestor: &Path,
+    target_tree_directory: &Path,
+    files: &[PathBuf],
+    options: &Options,
+    jobs: usize,
+    manifest: &mut Manifest,
+    report: &mut BuildReport,
+) {
+    let next_file = AtomicUsize::new(0);
+    let previous_build = &*manifest;
+    let mut results: Vec<_> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.clamp(1, files.len().max(1)))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let index = next_file.fetch_add(1, Ordering::Relaxed);
+                        let Some(file) = files.get(index) else {
+                            return results;
+                        };
+                        let result = build_file(
+                            ancestor,
+                            target_tree_directory,
+                            file,
+                            options,
+                            previous_build,
+                        );
+                        results.push((index, result));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect()
+    });
+    results.sort_by_key(|(index, _)| *index);
+    for (index, result) in results {
+        // a file which failed keeps its output from a previous build
+        let source_path = files[index].strip_prefix(ancestor).unwrap_or(&files[index]);
+        manifest.record_output(&output_path(source_path, options));
+        match result {
+            Ok((built, stamp)) => {
+                manifest.record(source_path, stamp);
+                match built {
+                    Built::Converted => report.converted += 1,
+                    Built::Copied => report.copied += 1,
+                    Built::Unchanged => report.unchanged += 1,
+                }
+            }
+            Err(e) => report.errors.push(e),
+        }
+    }
+}
+
+/// Convert a gemini file to its html file in the target tree, or copy another file,
+/// unless it is unchanged since the previous build
+/// return the stamp of the file, to be recorded in the manifest
+fn build_file(
+    ancestor: &Path,
+    target_tree_directory: &Path,
+    file: &Path,
+    options: &Options,
+    manifest: &Manifest,
+) -> Result<(Built, Option<Stamp>)> {
+    // convert gemini file (`.gmi` extension), copy other files (images...)
+    let is_gemini = file.extension() == Some(OsStr::new(&options.gemini_extension));
+    let source_path =
+        file.strip_prefix(ancestor)
+            .map_err(|source| Gemini2HtmlError::StripPrefix {
+                path: file.to_path_buf(),
+                prefix: ancestor.to_path_buf(),
+                source,
+            })?;
+    let target_file = target_tree_directory.join(output_path(source_path, options));
+    let stamp = manifest.stamp(source_path, file);
+    let built = if manifest.is_up_to_date(source_path, stamp, &target_file) {
+        info!("- 💤 unchanged since the previous build {:?}", file);
+        Built::Unchanged
+    } else if is_gemini {
+        info!("- ⏩ convert {:?} to html", file);
+        convert_page(file, Some(source_path), &target_file, options)?;
+        Built::Converted
+    } else {
+        info!("- 🍝 copy non gemini file : {:?}", target_file);
+        fs::copy(file, &target_file).map_err(Gemini2HtmlError::io(Operation::Copy, file))?;
+        Built::Copied
+    };
+    Ok((built, stamp))
+}
+
+/// Replace ancestor directory by another in a Path
+fn replace_ancestor(
+    source_ancestor: &Path,
+    target_ancestor: &Path,
+    path: &Path,
+) -> Result<PathBuf> {
+    let target_without_ancestor =
+        path.strip_prefix(source_ancestor)
+            .map_err(|source| Gemini2HtmlError::StripPrefix {
+                path: path.to_path_buf(),
+                prefix: source_ancestor.to_path_buf(),
+                source,
+            })?;
+    let final_target = Path::new(target_ancestor).join(target_without_ancestor);
+    Ok(final_target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_tree::TestTree;
+    use insta::assert_snapshot;
+
+    #[test]
+    fn test_replace_ancestor() {
+        let origin_ancestor = Path::new("./origin");
+        let target = Path::new("./target");
+        let path = Path::new("./origin/some_entry");
+        let replaced_path = replace_ancestor(origin_ancestor, target, path).unwrap();
+        assert_eq!(replaced_path, Path::new("./target/some_entry"));
+    }
+    #[test]
+    fn test_convert_tree() {
+        let source_tree_directory = Path::new("./tests");
+        let target_tree_directory = Path::new("./output_tests");
+        if target_tree_directory.is_dir() {
+            fs::remove_dir_all(target_tree_directory)
+                .expect("unable to purge old test dir ./output_tests");
+        }
+        fs::create_dir(target_tree_directory).expect("unable to create ./output_tests");
+        let report = build_site(
+            source_tree_directory,
+            target_tree_directory,
+            &Options::default(),
+            &SiteOptions::default(),
+            default_jobs(),
+            false,
+        );
+        assert!(report.is_success(), "{:?}", report.errors);
+        let files: Vec<&Path> = vec![
+            // ⛔ ⬇️
+            Path::new("./output_tests/gemini_file.html"),
+            Path::new("./output_tests/non_gemini_file.txt"),
+            Path::new("./output_tests/subdir/subfile.html"),
+            Path::new("./output_tests/subdir/subsubdir/subfile.html"),
+            // ⬇️  always append here, or it will break cargo insta snapshots order
+            // 🟢
+        ];
+        // highlighting changes the css and the code blocks, it has its own snapshots
+        let mut settings = insta::Settings::clone_current();
+        if cfg!(feature = "highlight") {
+            settings.set_snapshot_suffix("highlight");
+        }
+        let _settings = settings.bind_to_scope();
+        for file in files {
+            let content = fs::read_to_string(file).expect("unable to read file in ./output_tests");
+            assert_snapshot!(content);
+        }
+        fs::remove_dir_all(target_tree_directory)
+            .expect("unable to purge old test dir ./output_tests");
+    }
+    #[test]
+    fn test_local_link_path() {
+        assert_eq!(local_link_path("https://fqdn/a.gmi"), None);
+        assert_eq!(local_link_path("gemini://fqdn/a.gmi"), None);
+        assert_eq!(local_link_path("mailto:me@fqdn"), None);
+        assert_eq!(local_link_path("//fqdn/a.gmi"), None);
+        assert_eq!(local_link_path("#anchor"), None);
+        assert_eq!(local_link_path("a.gmi#anchor"), Some("a.gmi"));
+        assert_eq!(local_link_path("../b/a.gmi?q=1"), Some("../b/a.gmi"));
+        assert_eq!(local_link_path("/images/a.png"), Some("/images/a.png"));
+    }
+    #[test]
+    fn test_percent_encode() {
+        assert_eq!(
+            percent_encode("2026-10-01 100% été?#.gmi"),
+            "2026-10-01%20100%25%20%C3%A9t%C3%A9%3F%23.gmi"
+        );
+        assert_eq!(
+            percent_decode(&percent_encode("a b%c.gmi")).as_deref(),
+            Some("a b%c.gmi")
+        );
+        assert_eq!(
+            resolve_local_link(Path::new("/root"), Path::new("/root/d"), "a%20b.gmi"),
+            Path::new("/root/d/a b.gmi")
+        );
+        assert_eq!(
+            resolve_local_link(Path::new("/root"), Path::new("/root/d"), "100%.gmi"),
+            Path::new("/root/d/100%.gmi")
+        );
+    }
+    #[test]
+    fn test_check_site() {
+        let report = check_site(
+            Path::new("./tests"),
+            &Options::default(),
+            &SiteOptions::default(),
+        );
+        assert_eq!(report.checked, 3);
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        // the fixture links to a file which is not in subdir
+        assert_eq!(
+            report.broken_links,
+            vec![BrokenLink {
+                file: PathBuf::from("./tests/gemini_file.gmi"),
+                url: "subdir/non_gemini_file.txt".to_string(),
+            }]
+        );
+    }
+    #[test]
+    fn test_build_site_collects_errors() {
+        let source_tree_directory = TestTree::new("errors_source");
+        let target_tree_directory = TestTree::new("errors_target");
+        // a non utf-8 gemini file can't be converted, but the build goes on
+        fs::write(source_tree_directory.join("broken.gmi"), [0xff, 0xfe])
+            .expect("unable to write test file");
+        fs::write(source_tree_directory.join("valid.gmi"), "# valid")
+            .expect("unable to write test file");
+        let report = build_site(
+            &source_tree_directory,
+            &target_tree_directory,
+            &Options::default(),
+            &SiteOptions::default(),
+            default_jobs(),
+            false,
+        );
+        assert_eq!(report.converted, 1);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].operation(), Some(Operation::Read));
+        assert_eq!(
+            report.errors[0].path(),
+            Some(source_tree_directory.join("broken.gmi").as_path())
+        );
+        assert!(target_tree_directory.join("valid.html").is_file());
+        assert!(!target_tree_directory.join("broken.html").exists());
+    }
+    #[test]
+    fn test_build_site_skips_configuration() {
+        let source_tree_directory = TestTree::new("config_source");
+        let target_tree_directory = TestTree::new("config_target");
+        fs::create_dir(source_tree_directory.join("subdir"))
+            .expect("unable to create test directory");
+        for path in [
+            source_tree_directory.join(CONFIG_FILE_NAME),
+            source_tree_directory.join("subdir").join(CONFIG_FILE_NAME),
+        ] {
+            fs::write(path, "extension = \"gmi\"").expect("unable to write test file");
+        }
+        let report = build_site(
+            &source_tree_directory,
+            &target_tree_directory,
+            &Options::default(),
+            &SiteOptions::default(),
+            default_jobs(),
+            false,
+        );
+        assert!(report.is_success());
+        // only the configuration at the root of the capsule is private
+        assert_eq!(report.copied, 1);
+        assert!(!target_tree_directory.join(CONFIG_FILE_NAME).exists());
+        assert!(
+            target_tree_directory
+                .join("subdir")
+                .join(CONFIG_FILE_NAME)
+                .is_file()
+        );
+    }
+    #[test]
+    fn test_build_site_with_target_inside_source() {
+        let source_tree_directory = TestTree::new("nested_source");
+        let target_tree_directory = source_tree_directory.join("public");
+        fs::create_dir(&target_tree_directory).expect("unable to create test directory");
+        fs::write(source_tree_directory.join("index.gmi"), "# index")
+            .expect("unable to write test file");
+        // a previous build left some output, it must not be converted again
+        fs::write(target_tree_directory.join("old.gmi"), "# old")
+            .expect("unable to write test file");
+        // the target is reached through `..`, only canonical paths can be compared
+        let report = build_site(
+            &source_tree_directory,
+            &source_tree_directory.join("public/../public"),
+            &Options::default(),
+            &SiteOptions::default(),
+            default_jobs(),
+            false,
+        );
+        assert!(report.is_success());
+        assert_eq!(report.converted, 1);
+        assert!(target_tree_directory.join("index.html").is_file());
+        assert!(!target_tree_directory.join("public").exists());
+        assert!(!target_tree_directory.join("old.html").exists());
+    }
+    #[test]
+    fn test_build_site_refuses_target_containing_source() {
+        let target_tree_directory = TestTree::new("nested_target");
+        let source_tree_directory = target_tree_directory.join("capsule");
+        fs::create_dir(&source_tree_directory).expect("unable to create test directory");
+        fs::write(source_tree_directory.join("index.gmi"), "# index")
+            .expect("unable to write test file");
+        for target in [&target_tree_directory, &*source_tree_directory] {
+            let report = build_site(
+                &source_tree_directory,
+                target,
+                &Options::default(),
+                &SiteOptions::default(),
+                default_jobs(),
+                false,
+            );
+            assert_eq!(report.converted, 0);
+            assert_eq!(report.errors.len(), 1);
+            assert!(matches!(
+                report.errors[0],
+                Gemini2HtmlError::NestedDirectories { .. }
+            ));
+        }
+        assert!(!target_tree_directory.join("index.html").exists());
+        assert!(!source_tree_directory.join("index.html").exists());
+    }
+    #[test]
+    fn test_build_site_with_shared_stylesheet() {
+        let source_tree_directory = TestTree::new("stylesheet_source");
+        let target_tree_directory = TestTree::new("stylesheet_target");
+        fs::create_dir(source_tree_directory.join("posts"))
+            .expect("unable to create test directory");
+        fs::write(source_tree_directory.join("index.gmi"), "# index")
+            .expect("unable to write test file");
+        fs::write(source_tree_directory.join("posts/first.gmi"), "# first")
+            .expect("unable to write test file");
+        let options = Options {
+            stylesheet: Stylesheet::Shared,
+            extra_css: Some("nav { display: none; }\n".to_string()),
+            ..Options::default()
+        };
+        let report = build_site(
+            &source_tree_directory,
+            &target_tree_directory,
+            &options,
+            &SiteOptions::default(),
+            default_jobs(),
+            false,
+        );
+        assert!(report.is_success());
+        let css = fs::read_to_string(target_tree_directory.join(STYLESHEET_FILE_NAME)).unwrap();
+        assert!(css.starts_with("html {"));
+        assert!(css.ends_with("nav { display: none; }\n"));
+        let index = fs::read_to_string(target_tree_directory.join("index.html")).unwrap();
+        assert!(index.contains("<link rel=\"stylesheet\" href=\"style.css\" />"));
+        assert!(!index.contains("<style>"));
+        let post = fs::read_to_string(target_tree_directory.join("posts/first.html")).unwrap();
+        assert!(post.contains("<link rel=\"stylesheet\" href=\"../style.css\" />"));
+    }
+    #[test]
+    fn test_build_site_incremental() {
+        let source_tree_directory = TestTree::new("incremental_source");
+        let target_tree_directory = TestTree::new("incremental_target");
+        fs::create_dir(source_tree_directory.join("posts"))
+            .expect("unable to create test directory");
+        for (name, content) in [
+            ("about.gmi", "# about"),
+            ("image.png", "png"),
+            ("posts/2026-10-01-first.gmi", "# first"),
+        ] {
+            fs::write(source_tree_directory.join(name), content)
+                .expect("unable to write test file");
+        }
+        let site_options = SiteOptions {
+            gemlog: Some(GemlogOptions {
+                posts: PathBuf::from("posts"),
+                ..GemlogOptions::default()
+            }),
+            ..SiteOptions::default()
+        };
+        let report = build_site(
+            &source_tree_directory,
+            &target_tree_directory,
+            &Options::default(),
+            &site_options,
+            default_jobs(),
+            false,
+        );
+        assert!(report.is_success(), "{:?}", report.errors);
+        assert_eq!(
+            (report.converted, report.copied, report.unchanged),
+            (2, 1, 0)
+        );
+        // a page which is not rewritten keeps what we put in it
+        let index = target_tree_directory.join("posts/index.html");
+        let about = target_tree_directory.join("about.html");
+        for path in [&index, &about] {
+            fs::write(path, "not rewritten").expect("unable to write test file");
+        }
+        let report = build_site(
+            &source_tree_directory,
+            &target_tree_directory,
+            &Options::default(),
+            &site_options,
+            default_jobs(),
+            false,
+        );
+        assert!(report.is_success(), "{:?}", report.errors);
+        assert_eq!(
+            (report.converted, report.copied, report.unchanged),
+            (0, 0, 3)
+        );
+        assert_eq!(fs::read_to_string(&index).unwrap(), "not rewritten");
+        // a new post changes the index, but not the other pages
+        fs::write(
+            source_tree_directory.join("posts/2026-10-02-second.gmi"),
+            "# second",
+        )
+        .expect("unable to write test file");
+        let report = build_site(
+            &source_tree_directory,
+            &target_tree_directory,
+            &Options::default(),
+            &site_options,
+            default_jobs(),
+            false,
+        );
+        assert_eq!(
+            (report.converted, report.copied, report.unchanged),
+            (1, 0, 3)
+        );
+        assert!(fs::read_to_string(&index).unwrap().contains("second"));
+        assert_eq!(fs::read_to_string(&about).unwrap(), "not rewritten");
+        // a missing output is written again
+        fs::remove_file(target_tree_directory.join("image.png"))
+            .expect("unable to remove test file");
+        let report = build_site(
+            &source_tree_directory,
+            &target_tree_directory,
+            &Options::default(),
+            &site_options,
+            default_jobs(),
+            false,
+        );
+        assert_eq!(
+            (report.converted, report.copied, report.unchanged),
+            (0, 1, 3)
+        );
+        // other options change every page
+        let options = Options {
+            credits: "my credits".to_string(),
+            ..Options::default()
+        };
+        let report = build_site(
+            &source_tree_directory,
+            &target_tree_directory,
+            &options,
+            &site_options,
+            default_jobs(),
+            false,
+        );
+        assert_eq!(
+            (report.converted, report.copied, report.unchanged),
+            (3, 1, 0)
+        );
+        assert!(fs::read_to_string(&about).unwrap().contains("my credits"));
+        // and so does a forced build
+        let report = build_site(
+            &source_tree_directory,
+            &target_tree_directory,
+            &options,
+            &site_options,
+            default_jobs(),
+            true,
+        );
+        assert_eq!(
+            (report.converted, report.copied, report.unchanged),
+            (3, 1, 0)
+        );
+    }
+    #[test]
+    fn test_hand_written_gemlog_index() {
+        let source_tree_directory = TestTree::new("hand_written_index_source");
+        let target_tree_directory = TestTree::new("hand_written_index_target");
+        fs::create_dir(source_tree_directory.join("posts"))
+            .expect("unable to create test directory");
+        let hand_written = "# My posts\n\n=> 2026-10-01-first.gmi the first one\n";
+        for (name, content) in [
+            ("posts/index.gmi", hand_written),
+            ("posts/2026-10-01-first.gmi", "# first"),
+        ] {
+            fs::write(source_tree_directory.join(name), content)
+                .expect("unable to write test file");
+        }
+        let site_options = SiteOptions {
+            gemlog: Some(GemlogOptions {
+                posts: PathBuf::from("posts"),
+                ..GemlogOptions::default()
+            }),
+            ..SiteOptions::default()
+        };
+        let report = build_site(
+            &source_tree_directory,
+            &target_tree_directory,
+            &Options::default(),
+            &site_options,
+            default_jobs(),
+            false,
+        );
+        // the index is reported, left untouched, and built as any page
+        assert!(matches!(
+            report.errors.as_slice(),
+            [Gemini2HtmlError::Gemlog { .. }]
+        ));
+        assert_eq!(report.converted, 2);
+        assert_eq!(
+            fs::read_to_string(source_tree_directory.join("posts/index.gmi")).unwrap(),
+            hand_written
+        );
+        let index = fs::read_to_string(target_tree_directory.join("posts/index.html")).unwrap();
+        assert!(index.contains("the first one"));
+    }
+    #[test]
+    fn test_build_site_in_parallel() {
+        let source_tree_directory = TestTree::new("parallel_source");
+        let target_tree_directories = [
+            TestTree::new("parallel_target_1"),
+            TestTree::new("parallel_target_4"),
+        ];
+        // every third file is not utf-8, it can't be converted
+        for page in 0..30 {
+            let path = source_tree_directory.join(format!("page_{page:02}.gmi"));
+            match page % 3 {
+                0 => fs::write(path, [0xff, 0xfe]),
+                _ => fs::write(path, format!("# page {page}")),
+            }
+            .expect("unable to write test file");
+        }
+        let mut reports = Vec::new();
+        for (target_tree_directory, jobs) in target_tree_directories.iter().zip([1, 4]) {
+            let report = build_site(
+                &source_tree_directory,
+                target_tree_directory,
+                &Options::default(),
+                &SiteOptions::default(),
+                jobs,
+                false,
+            );
+            assert_eq!(report.converted, 20);
+            let failed: Vec<_> = report
+                .errors
+                .iter()
+                .filter_map(|e| e.path().map(Path::to_path_buf))
+                .collect();
+            reports.push(failed);
+        }
+        // errors come in the order of the files, whatever the number of threads
+        let expected: Vec<_> = (0..30)
+            .step_by(3)
+            .map(|page| source_tree_directory.join(format!("page_{page:02}.gmi")))
+            .collect();
+        assert_eq!(reports, [expected.clone(), expected]);
+        for page in [1, 29] {
+            let page = format!("page_{page:02}.html");
+            assert_eq!(
+                fs::read_to_string(target_tree_directories[0].join(&page)).unwrap(),
+                fs::read_to_string(target_tree_directories[1].join(&page)).unwrap()
+            );
+        }
+    }
+    #[test]
+    fn test_prune_stale_outputs() {
+        let source_tree_directory = TestTree::new("prune_source");
+        let target_tree_directory = TestTree::new("prune_target");
+        fs::create_dir(source_tree_directory.join("old")).expect("unable to create test directory");
+        for name in ["index.gmi", "image.png", "old/page.gmi"] {
+            fs::write(source_tree_directory.join(name), "# page")
+                .expect("unable to write test file");
+        }
+        let report = build_site(
+            &source_tree_directory,
+            &target_tree_directory,
+            &Options::default(),
+            &SiteOptions::default(),
+            default_jobs(),
+            false,
+        );
+        assert!(report.stale.is_empty());
+        // files which don't come from the build are never stale
+        fs::write(target_tree_directory.join("CNAME"), "example.org")
+            .expect("unable to write test file");
+        fs::remove_file(source_tree_directory.join("image.png"))
+            .expect("unable to remove test file");
+        fs::remove_dir_all(source_tree_directory.join("old"))
+            .expect("unable to remove test directory");
+        // stale outputs are listed at each build, until they are removed
+        for _ in 0..2 {
+            let report = build_site(
+                &source_tree_directory,
+                &target_tree_directory,
+                &Options::default(),
+                &SiteOptions::default(),
+                default_jobs(),
+                false,
+            );
+            assert_eq!(
+                report.stale,
+                [PathBuf::from("image.png"), PathBuf::from("old/page.html")]
+            );
+            assert!(target_tree_directory.join("old/page.html").is_file());
+        }
+        let mut report = build_site(
+            &source_tree_directory,
+            &target_tree_directory,
+            &Options::default(),
+            &SiteOptions::default(),
+            default_jobs(),
+            false,
+        );
+        prune(&target_tree_directory, &mut report);
+        assert!(report.is_success(), "{:?}", report.errors);
+        assert_eq!(report.removed, 2);
+        assert!(!target_tree_directory.join("image.png").exists());
+        assert!(!target_tree_directory.join("old").exists());
+        assert!(target_tree_directory.join("index.html").is_file());
+        assert!(target_tree_directory.join("CNAME").is_file());
+        let report = build_site(
+            &source_tree_directory,
+            &target_tree_directory,
+            &Options::default(),
+            &SiteOptions::default(),
+            default_jobs(),
+            false,
+        );
+        assert!(report.stale.is_empty());
+    }
+    #[cfg(unix)]
+    #[test]
+    fn test_unreadable_directory_is_not_stale() {
+        use std::os::unix::fs::PermissionsExt;
+        let source_tree_directory = TestTree::new("unreadable_source");
+        let target_tree_directory = TestTree::new("unreadable_target");
+        let directory = source_tree_directory.join("private");
+        fs::create_dir(&directory).expect("unable to create test directory");
+        fs::write(directory.join("page.gmi"), "# page").expect("unable to write test file");
+        let build = || {
+            build_site(
+                &source_tree_directory,
+                &target_tree_directory,
+                &Options::default(),
+                &SiteOptions::default(),
+                default_jobs(),
+                false,
+            )
+        };
+        assert!(build().is_success());
+        let set_mode = |mode| {
+            fs::set_permissions(&directory, fs::Permissions::from_mode(mode))
+                .expect("unable to change test directory permissions")
+        };
+        set_mode(0o000);
+        // root reads it anyway, there is nothing to test
+        if fs::read_dir(&directory).is_ok() {
+            set_mode(0o755);
+            return;
+        }
+        let mut report = build();
+        set_mode(0o755);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].operation(), Some(Operation::ReadDir));
+        assert!(report.stale.is_empty());
+        prune(&target_tree_directory, &mut report);
+        assert!(target_tree_directory.join("private/page.html").is_file());
+        // its output is still known once the directory can be read again
+        fs::remove_dir_all(&directory).expect("unable to remove test directory");
+        assert_eq!(build().stale, [PathBuf::from("private/page.html")]);
+    }
+    #[test]
+    fn test_build_site_with_ignore_rules() {
+        let source_tree_directory = TestTree::new("ignore_source");
+        let target_tree_directory = TestTree::new("ignore_target");
+        fs::create_dir(source_tree_directory.join(".git"))
+            .expect("unable to create test directory");
+        for (name, content) in [
+            (IGNORE_FILE_NAME, "# version control\n.git/\n*.swp\n"),
+            (".git/HEAD", "ref: refs/heads/main"),
+            ("index.gmi", "# index"),
+            (".index.gmi.swp", "swap"),
+            ("post.draft.gmi", "# draft"),
+            ("notes.txt", "private"),
+        ] {
+            fs::write(source_tree_directory.join(name), content)
+                .expect("unable to write test file");
+        }
+        let site_options = SiteOptions {
+            exclude: vec!["notes.txt".to_string()],
+            ..SiteOptions::default()
+        };
+        let report = build_site(
+            &source_tree_directory,
+            &target_tree_directory,
+            &Options::default(),
+            &site_options,
+            default_jobs(),
+            false,
+        );
+        assert!(report.is_success(), "{:?}", report.errors);
+        assert_eq!((report.converted, report.copied), (1, 0));
+        let mut outputs: Vec<_> = fs::read_dir(&target_tree_directory)
+            .unwrap()
+            .map(|direntry| direntry.unwrap().file_name())
+            .collect();
+        outputs.sort();
+        assert_eq!(outputs, [".gemini2html-manifest", "index.html"]);
+        assert_eq!(
+            check_site(&source_tree_directory, &Options::default(), &site_options).checked,
+            1
+        );
+        // drafts are built on demand, without building the other pages again
+        let site_options = SiteOptions {
+            drafts: true,
+            ..site_options
+        };
+        let report = build_site(
+            &source_tree_directory,
+            &target_tree_directory,
+            &Options::default(),
+            &site_options,
+            default_jobs(),
+            false,
+        );
+        assert_eq!((report.converted, report.unchanged), (1, 1));
+        assert!(target_tree_directory.join("post.draft.html").is_file());
+    }
+}
@@ -0,0 +1,466 @@
+//! Page templates
+//!
+//! A template is a html page with a few tags :
+//! `{{ variable }}` inserts a value, escaped unless it is already html,
+//! and `{{#if variable}} ... {{else}} ... {{/if}}` keeps a part only if the value is not empty.
+//!
+//! ```html
+//! <html lang="{{ language }}">
+//! <title>{{#if title}}{{ title }} - {{/if}}{{ site_name }}</title>
+//! {{ style }}
+//! <nav>{{ breadcrumbs }}</nav>
+//! {{ content }}
+//! <footer>{{ source_path }}, {{ last_modified }}</footer>
+//! ```
+//!
+//! Nothing else is evaluated, an unknown variable or tag is an error.
+
+use crate::{
+    Gemini2HtmlError, Operation, Options, Result, escaped_attribute, escaped_text, html_style,
+};
+use std::fs;
+use std::path::{Component, Path};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A value which can be used in a template
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variable {
+    /// Title of the page, from its first level 1 heading
+    Title,
+    SiteName,
+    BaseUrl,
+    Language,
+    Author,
+    /// The converted page, html
+    Content,
+    /// Links to the parent directories, html
+    Breadcrumbs,
+    /// Date of the last modification of the gemini file, `YYYY-MM-DD`
+    LastModified,
+    /// Path of the gemini file, relative to the root of the capsule
+    SourcePath,
+    /// The stylesheet, html
+    Style,
+    /// The footer, html
+    Credits,
+}
+
+impl Variable {
+    fn from_name(name: &str) -> Option<Variable> {
+        match name {
+            "title" => Some(Variable::Title),
+            "site_name" => Some(Variable::SiteName),
+            "base_url" => Some(Variable::BaseUrl),
+            "language" => Some(Variable::Language),
+            "author" => Some(Variable::Author),
+            "content" => Some(Variable::Content),
+            "breadcrumbs" => Some(Variable::Breadcrumbs),
+            "last_modified" => Some(Variable::LastModified),
+            "source_path" => Some(Variable::SourcePath),
+            "style" => Some(Variable::Style),
+            "credits" => Some(Variable::Credits),
+            _ => None,
+        }
+    }
+
+    /// Html values are inserted as is, the others are escaped
+    fn is_html(self) -> bool {
+        matches!(
+            self,
+            Variable::Content | Variable::Breadcrumbs | Variable::Style | Variable::Credits
+        )
+    }
+}
+
+/// A part of a template
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Variable(Variable),
+    If {
+        variable: Variable,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+/// A parsed template, see the module documentation for its syntax
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    nodes: Vec<Node>,
+    /// The text it was parsed from
+    source: String,
+}
+
+/// An `{{#if}}` being parsed
+struct OpenIf {
+    variable: Variable,
+    line: usize,
+    then: Vec<Node>,
+    /// Set after `{{else}}`
+    otherwise: Option<Vec<Node>>,
+}
+
+/// Where a page comes from, given to templates
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Source<'a> {
+    /// Path of the gemini file, relative to the root of the capsule
+    pub(crate) path: Option<&'a Path>,
+    pub(crate) last_modified: Option<SystemTime>,
+}
+
+/// Everything a template can show about a page
+pub(crate) struct Context<'a> {
+    pub(crate) title: Option<&'a str>,
+    pub(crate) content: &'a str,
+    pub(crate) source: &'a Source<'a>,
+    pub(crate) options: &'a Options,
+}
+
+impl Template {
+    /// Read a template file
+    pub fn load(path: &Path) -> Result<Template> {
+        let content =
+            fs::read_to_string(path).map_err(Gemini2HtmlError::io(Operation::Read, path))?;
+        Template::parse(&content).map_err(|(line, message)| Gemini2HtmlError::Template {
+            path: path.to_path_buf(),
+            line,
+            message,
+        })
+    }
+
+    /// Parse a template, errors are returned with their line number
+    pub fn parse(content: &str) -> std::result::Result<Template, (usize, String)> {
+        let mut nodes = Vec::new();
+        let mut open_ifs: Vec<OpenIf> = Vec::new();
+        let mut line = 1;
+        let mut rest = content;
+        while !rest.is_empty() {
+            let (text, tag) = match rest.split_once("{{") {
+                Some((text, after)) => {
+                    let (tag, after) = after.split_once("}}").ok_or((
+                        line + text.matches('\n').count(),
+                        "unclosed tag".to_string(),
+                    ))?;
+                    rest = after;
+                    (text, Some(tag))
+                }
+                None => (std::mem::take(&mut rest), None),
+            };
+            if !text.is_empty() {
+                innermost(&mut nodes, &mut open_ifs).push(Node::Text(text.to_string()));
+            }
+            line += text.matches('\n').count();
+            let Some(tag) = tag else {
+                break;
+            };
+            let tag_line = line;
+            line += tag.matches('\n').count();
+            let variable = |name: &str| {
+                Variable::from_name(name.trim())
+                    .ok_or((tag_line, format!("unknown variable `{}`", name.trim())))
+            };
+            match tag.trim() {
+                "else" => match open_ifs.last_mut() {
+                    Some(open_if) if open_if.otherwise.is_none() => {
+                        open_if.otherwise = Some(Vec::new())
+                    }
+                    _ => return Err((tag_line, "unexpected `{{else}}`".to_string())),
+                },
+                "/if" => {
+                    let open_if = open_ifs
+                        .pop()
+                        .ok_or((tag_line, "unexpected `{{/if}}`".to_string()))?;
+                    let node = Node::If {
+                        variable: open_if.variable,
+                        then: open_if.then,
+                        otherwise: open_if.otherwise.unwrap_or_default(),
+                    };
+                    innermost(&mut nodes, &mut open_ifs).push(node);
+                }
+                tag => match tag.strip_prefix("#if ") {
+                    Some(name) => open_ifs.push(OpenIf {
+                        variable: variable(name)?,
+                        line: tag_line,
+                        then: Vec::new(),
+                        otherwise: None,
+                    }),
+                    None => {
+                        innermost(&mut nodes, &mut open_ifs).push(Node::Variable(variable(tag)?))
+                    }
+                },
+            }
+        }
+        match open_ifs.pop() {
+            Some(open_if) => Err((open_if.line, "unclosed `{{#if}}`".to_string())),
+            None => Ok(Template {
+                nodes,
+                source: content.to_string(),
+            }),
+        }
+    }
+
+    /// The text the template was parsed from
+    pub(crate) fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// True if the pages show the modification time of their gemini file
+    pub(crate) fn uses_last_modified(&self) -> bool {
+        uses(&self.nodes, Variable::LastModified)
+    }
+
+    /// Fill the template with the values of a page
+    pub(crate) fn render(&self, context: &Context) -> String {
+        let mut html = String::new();
+        render_nodes(&self.nodes, context, &mut html);
+        html
+    }
+}
+
+/// Nodes being parsed go to the innermost `{{#if}}`
+fn innermost<'a>(nodes: &'a mut Vec<Node>, open_ifs: &'a mut [OpenIf]) -> &'a mut Vec<Node> {
+    match open_ifs.last_mut() {
+        Some(open_if) => open_if.otherwise.as_mut().unwrap_or(&mut open_if.then),
+        None => nodes,
+    }
+}
+
+/// True if a variable is used by the nodes, or by the nodes of their `{{#if}}`s
+fn uses(nodes: &[Node], variable: Variable) -> bool {
+    nodes.iter().any(|node| match node {
+        Node::Text(_) => false,
+        Node::Variable(used) => *used == variable,
+        Node::If {
+            variable: used,
+            then,
+            otherwise,
+        } => *used == variable || uses(then, variable) || uses(otherwise, variable),
+    })
+}
+
+fn render_nodes(nodes: &[Node], context: &Context, html: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => html.push_str(text),
+            Node::Variable(variable) => {
+                let value = value(*variable, context);
+                match variable.is_html() {
+                    true => html.push_str(&value),
+                    false => html.push_str(&escaped_attribute(&value)),
+                }
+            }
+            Node::If {
+                variable,
+                then,
+                otherwise,
+            } => match value(*variable, context).is_empty() {
+                false => render_nodes(then, context, html),
+                true => render_nodes(otherwise, context, html),
+            },
+        }
+    }
+}
+
+/// Value of a variable for a page, empty if unknown
+fn value(variable: Variable, context: &Context) -> String {
+    let options = context.options;
+    let text = |value: &Option<String>| value.clone().unwrap_or_default();
+    match variable {
+        Variable::Title => context.title.unwrap_or_default().to_string(),
+        Variable::SiteName => text(&options.site_name),
+        Variable::BaseUrl => text(&options.base_url),
+        Variable::Language => text(&options.language),
+        Variable::Author => text(&options.author),
+        Variable::Content => context.content.to_string(),
+        Variable::Breadcrumbs => context
+            .source
+            .path
+            .map(|path| breadcrumbs(path, context.title, options.site_name.as_deref()))
+            .unwrap_or_default(),
+        Variable::LastModified => context
+            .source
+            .last_modified
+            .map(iso_date)
+            .unwrap_or_default(),
+        Variable::SourcePath => context
+            .source
+            .path
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        Variable::Style => html_style(context.source, options),
+        Variable::Credits => options.credits.clone(),
+    }
+}
+
+/// Links from the root of the capsule to a page, through the `index.html` of each directory
+/// the last item, the page itself, is not a link
+fn breadcrumbs(path: &Path, title: Option<&str>, site_name: Option<&str>) -> String {
+    let directories: Vec<String> = path
+        .parent()
+        .into_iter()
+        .flat_map(Path::components)
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect();
+    let mut crumbs = vec![site_name.unwrap_or("home").to_string()];
+    crumbs.extend(directories);
+    // an index page is the page of its directory
+    let is_index = path.file_stem().is_some_and(|stem| stem == "index");
+    if !is_index {
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string());
+        crumbs.push(title.map(str::to_string).or(stem).unwrap_or_default());
+    }
+    let depth = crumbs.len() - 1;
+    let mut links: Vec<String> = crumbs[..depth]
+        .iter()
+        .enumerate()
+        .map(|(index, crumb)| {
+            // the page is in the last directory, or in the parent of an index
+            let up = depth - index - usize::from(!is_index);
+            format!(
+                "<a href=\"{}index.html\">{}</a>",
+                "../".repeat(up),
+                escaped_text(crumb)
+            )
+        })
+        .collect();
+    links.push(escaped_text(&crumbs[depth]));
+    links.join(" / ")
+}
+
+/// Date of a time, `YYYY-MM-DD` in UTC
+pub(crate) fn iso_date(time: SystemTime) -> String {
+    let days = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() / 86_400) as i64;
+    // days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn render(template: &str, context: &Context) -> String {
+        Template::parse(template).unwrap().render(context)
+    }
+
+    #[test]
+    fn test_render_template() {
+        let options = Options {
+            site_name: Some("My <capsule>".to_string()),
+            credits: "<b>me</b>".to_string(),
+            ..Options::default()
+        };
+        let source = Source {
+            path: Some(Path::new("posts/first.gmi")),
+            last_modified: Some(UNIX_EPOCH + Duration::from_secs(86_400 * 365)),
+        };
+        let context = Context {
+            title: Some("First \"post\""),
+            content: "<p>text</p>",
+            source: &source,
+            options: &options,
+        };
+        assert_eq!(
+            render(
+                "<title>{{ title }} - {{site_name}}</title>\n{{ content }}{{ credits }}",
+                &context
+            ),
+            "<title>First &quot;post&quot; - My &lt;capsule&gt;</title>\n<p>text</p><b>me</b>"
+        );
+        assert_eq!(
+            render("{{ source_path }} {{ last_modified }}", &context),
+            "posts/first.gmi 1971-01-01"
+        );
+        assert_eq!(
+            render(
+                "{{#if author}}by {{ author }}{{else}}anonymous{{/if}}",
+                &context
+            ),
+            "anonymous"
+        );
+        assert_eq!(
+            render(
+                "{{#if title}}{{#if site_name}}both{{/if}}{{/if}}{{#if base_url}}url{{/if}}",
+                &context
+            ),
+            "both"
+        );
+    }
+    #[test]
+    fn test_template_errors() {
+        assert_eq!(
+            Template::parse("a\n{{ unknown }}").unwrap_err(),
+            (2, "unknown variable `unknown`".to_string())
+        );
+        assert_eq!(
+            Template::parse("\n\n{{#if title}}").unwrap_err(),
+            (3, "unclosed `{{#if}}`".to_string())
+        );
+        assert_eq!(
+            Template::parse("{{ title").unwrap_err(),
+            (1, "unclosed tag".to_string())
+        );
+        assert!(Template::parse("{{/if}}").is_err());
+        assert!(Template::parse("{{else}}").is_err());
+        assert!(Template::parse("{{#if title}}{{else}}{{else}}{{/if}}").is_err());
+    }
+    #[test]
+    fn test_uses_last_modified() {
+        let uses = |template: &str| Template::parse(template).unwrap().uses_last_modified();
+        assert!(!uses("{{ title }} {{ source_path }}"));
+        assert!(uses("<footer>{{ last_modified }}</footer>"));
+        assert!(uses(
+            "{{#if title}}{{else}}{{#if last_modified}}x{{/if}}{{/if}}"
+        ));
+    }
+    #[test]
+    fn test_breadcrumbs() {
+        assert_eq!(breadcrumbs(Path::new("index.gmi"), None, None), "home");
+        assert_eq!(
+            breadcrumbs(Path::new("about.gmi"), Some("About me"), Some("Site")),
+            "<a href=\"index.html\">Site</a> / About me"
+        );
+        assert_eq!(
+            breadcrumbs(Path::new("posts/2026/first.gmi"), None, None),
+            "<a href=\"../../index.html\">home</a> / <a href=\"../index.html\">posts</a> / <a href=\"index.html\">2026</a> / first"
+        );
+        assert_eq!(
+            breadcrumbs(Path::new("posts/index.gmi"), None, None),
+            "<a href=\"../index.html\">home</a> / posts"
+        );
+    }
+    #[test]
+    fn test_iso_date() {
+        assert_eq!(iso_date(UNIX_EPOCH), "1970-01-01");
+        assert_eq!(
+            iso_date(UNIX_EPOCH + Duration::from_secs(1_709_208_000)),
+            "2024-02-29"
+        );
+        assert_eq!(
+            iso_date(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "2000-02-29"
+        );
+    }
+}
@@ -0,0 +1,45 @@
+//! Temporary directories of the tests
+
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// An empty directory, removed when dropped, even when the test fails
+pub(crate) struct TestTree {
+    root: PathBuf,
+}
+
+impl TestTree {
+    /// Create `gemini2html_<name>` in the temporary directory, a previous one is purged
+    /// names must be unique, tests run in parallel
+    pub(crate) fn new(name: &str) -> TestTree {
+        let root = std::env::temp_dir().join(format!("gemini2html_{name}"));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).expect("unable to create test directory");
+        TestTree { root }
+    }
+}
+
+impl Deref for TestTree {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl AsRef<Path> for TestTree {
+    fn as_ref(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl Drop for TestTree {
+    fn drop(&mut self) {
+        // a second panic would abort the tests, hiding the first one
+        if fs::remove_dir_all(&self.root).is_err() && !thread::panicking() {
+            panic!("unable to purge test directory {:?}", self.root);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! Built-in themes
+//!
+//! Each theme is a stylesheet with a light and a dark variant,
+//! the dark one is used when the reader prefers it (`prefers-color-scheme`).
+
+/// A stylesheet bundled with gemini2html
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Theme {
+    /// The original look, inspired by Ploum's website
+    #[default]
+    Default,
+    /// Monospace, like a terminal
+    Terminal,
+    /// Large text, strong contrast and visible focus, for accessibility
+    HighContrast,
+    /// Serif text, link targets are printed on paper
+    Print,
+}
+
+impl Theme {
+    /// Every theme, in the order of the documentation
+    pub const ALL: [Theme; 4] = [
+        Theme::Default,
+        Theme::Terminal,
+        Theme::HighContrast,
+        Theme::Print,
+    ];
+
+    /// Theme from its name, as given in the configuration
+    pub fn from_name(name: &str) -> Option<Theme> {
+        Theme::ALL.into_iter().find(|theme| theme.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::Default => "default",
+            Theme::Terminal => "terminal",
+            Theme::HighContrast => "high-contrast",
+            Theme::Print => "print",
+        }
+    }
+
+    /// The css of the theme
+    pub fn css(self) -> &'static str {
+        match self {
+            Theme::Default => include_str!("themes/default.css"),
+            Theme::Terminal => include_str!("themes/terminal.css"),
+            Theme::HighContrast => include_str!("themes/high-contrast.css"),
+            Theme::Print => include_str!("themes/print.css"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_theme_names() {
+        for theme in Theme::ALL {
+            assert_eq!(Theme::from_name(theme.name()), Some(theme));
+        }
+        assert_eq!(Theme::from_name("unknown"), None);
+    }
+    #[test]
+    fn test_themes_have_a_dark_variant() {
+        for theme in Theme::ALL {
+            assert!(
+                theme.css().contains("prefers-color-scheme:dark"),
+                "{} has no dark variant",
+                theme.name()
+            );
+        }
+    }
+}
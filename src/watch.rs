@@ -0,0 +1,378 @@
+//! Watch mode : rebuild the site when the source tree changes
+//!
+//! Every directory of the source tree is watched with inotify, except the target
+//! and ignored ones. Bursts of events, like an editor saving a file, are gathered
+//! into a single rebuild, which is incremental : only the affected files are written.
+//! The ignore rules are reloaded when the ignore file changes.
+
+use crate::config::CONFIG_FILE_NAME;
+use crate::ignore::{IGNORE_FILE_NAME, SourceFilter};
+use crate::site::{BuildReport, SiteOptions, build_filtered_site, nested_target};
+use crate::{Gemini2HtmlError, Operation, Options, Result};
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use log::{LevelFilter, error, info, warn};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Quiet time after the last event before a rebuild
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Changed files listed in a rebuild summary, the others are counted
+const LISTED_CHANGES: usize = 3;
+
+/// Watch the source tree and rebuild the target directory after each change,
+/// never returns unless the source tree can't be watched
+pub fn watch(
+    source_tree_directory: &Path,
+    target_tree_directory: &Path,
+    options: &Options,
+    site_options: &SiteOptions,
+    jobs: usize,
+) -> Result<()> {
+    let mut watcher = Watcher::new(source_tree_directory, target_tree_directory, site_options)?;
+    let report = build_filtered_site(
+        source_tree_directory,
+        target_tree_directory,
+        options,
+        site_options,
+        &watcher.filter,
+        jobs,
+        false,
+    );
+    info!("{}", summary(&[], &report));
+    info!("👀 watching {:?}, stop with ctrl-c", source_tree_directory);
+    loop {
+        let changes = watcher.wait_for_changes()?;
+        if changes
+            .iter()
+            .any(|path| path == Path::new(CONFIG_FILE_NAME))
+        {
+            warn!("{CONFIG_FILE_NAME} changed, restart watch to use it");
+        }
+        if changes
+            .iter()
+            .any(|path| path == Path::new(IGNORE_FILE_NAME))
+        {
+            match watcher.reload_filter(site_options) {
+                Ok(()) => info!("🙈 {IGNORE_FILE_NAME} changed, ignore rules reloaded"),
+                Err(e) => error!("{e}, the previous ignore rules are kept"),
+            }
+        }
+        // the summary replaces the log of every file, unless debugging
+        let level = log::max_level();
+        if level < LevelFilter::Debug {
+            log::set_max_level(level.min(LevelFilter::Warn));
+        }
+        let report = build_filtered_site(
+            source_tree_directory,
+            target_tree_directory,
+            options,
+            site_options,
+            &watcher.filter,
+            jobs,
+            false,
+        );
+        log::set_max_level(level);
+        info!("{}", summary(&changes, &report));
+        for e in &report.errors {
+            error!("  - {e}");
+        }
+    }
+}
+
+/// Inotify watches of the source tree
+struct Watcher {
+    inotify: Inotify,
+    /// Watched directories, relative to the root of the capsule
+    directories: BTreeMap<WatchDescriptor, PathBuf>,
+    source_tree_directory: PathBuf,
+    /// Target directory inside the source, relative to the root of the capsule
+    excluded: Option<PathBuf>,
+    /// Ignore rules, shared with the builds
+    filter: SourceFilter,
+}
+
+impl Watcher {
+    /// Watch every directory of the source tree
+    fn new(
+        source_tree_directory: &Path,
+        target_tree_directory: &Path,
+        site_options: &SiteOptions,
+    ) -> Result<Watcher> {
+        let excluded = match nested_target(source_tree_directory, target_tree_directory)? {
+            Some(target) => fs::canonicalize(source_tree_directory)
+                .ok()
+                .and_then(|source| Some(target.strip_prefix(source).ok()?.to_path_buf())),
+            None => None,
+        };
+        let inotify = Inotify::init().map_err(Gemini2HtmlError::io(
+            Operation::Watch,
+            source_tree_directory,
+        ))?;
+        let mut watcher = Watcher {
+            inotify,
+            directories: BTreeMap::new(),
+            source_tree_directory: source_tree_directory.to_path_buf(),
+            excluded,
+            filter: SourceFilter::load(source_tree_directory, site_options)?,
+        };
+        watcher.add_watches(Path::new(""))?;
+        Ok(watcher)
+    }
+
+    /// Watch a directory and its subdirectories, relative to the root of the capsule
+    /// only the directory itself is an error, a subdirectory which can't be watched
+    /// is skipped with a warning
+    fn add_watches(&mut self, directory: &Path) -> Result<()> {
+        let path = self.source_tree_directory.join(directory);
+        let mask = WatchMask::MODIFY
+            | WatchMask::CLOSE_WRITE
+            | WatchMask::CREATE
+            | WatchMask::DELETE
+            | WatchMask::MOVE;
+        let watch_descriptor = self
+            .inotify
+            .watches()
+            .add(&path, mask)
+            .map_err(Gemini2HtmlError::io(Operation::Watch, &path))?;
+        self.directories
+            .insert(watch_descriptor, directory.to_path_buf());
+        let tree = match fs::read_dir(&path) {
+            Ok(tree) => tree,
+            Err(e) => {
+                let e = Gemini2HtmlError::io(Operation::ReadDir, &path)(e);
+                warn!("{e}, its subdirectories are not watched");
+                return Ok(());
+            }
+        };
+        for direntry in tree.flatten() {
+            let subdirectory = directory.join(direntry.file_name());
+            if direntry.path().is_dir()
+                && !self.is_ignored(&subdirectory, true)
+                && let Err(e) = self.add_watches(&subdirectory)
+            {
+                warn!("{e}, its changes are not watched");
+            }
+        }
+        Ok(())
+    }
+
+    /// Load the ignore rules again, and watch the directories they don't ignore anymore
+    /// the directories they now ignore are still watched, but their events are dropped
+    fn reload_filter(&mut self, site_options: &SiteOptions) -> Result<()> {
+        self.filter = SourceFilter::load(&self.source_tree_directory, site_options)?;
+        // watching a directory again only gives its existing watch
+        self.add_watches(Path::new(""))
+    }
+
+    /// True if changes to a path don't need a rebuild
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.excluded
+            .as_ref()
+            .is_some_and(|excluded| path.starts_with(excluded))
+            || self.filter.is_excluded(path, is_dir)
+    }
+
+    /// Wait for changes to the source tree, until no event came for a while
+    /// return the changed paths, relative to the root of the capsule
+    fn wait_for_changes(&mut self) -> Result<Vec<PathBuf>> {
+        let mut changes = BTreeSet::new();
+        let mut buffer = [0; 4096];
+        let mut last_event = Instant::now();
+        while changes.is_empty() || last_event.elapsed() < DEBOUNCE {
+            let events = match changes.is_empty() {
+                true => self.inotify.read_events_blocking(&mut buffer),
+                false => self.inotify.read_events(&mut buffer),
+            };
+            let events: Vec<_> = match events {
+                Ok(events) => events.map(|event| event.to_owned()).collect(),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(DEBOUNCE / 4);
+                    continue;
+                }
+                Err(e) => {
+                    return Err(Gemini2HtmlError::io(
+                        Operation::Watch,
+                        &self.source_tree_directory,
+                    )(e));
+                }
+            };
+            for event in events {
+                // the watch of a removed directory
+                if event.mask.contains(EventMask::IGNORED) {
+                    self.directories.remove(&event.wd);
+                    continue;
+                }
+                let (Some(directory), Some(name)) = (self.directories.get(&event.wd), event.name)
+                else {
+                    continue;
+                };
+                let path = directory.join(name);
+                let is_dir = event.mask.contains(EventMask::ISDIR);
+                if self.is_ignored(&path, is_dir) {
+                    continue;
+                }
+                if is_dir
+                    && event
+                        .mask
+                        .intersects(EventMask::CREATE | EventMask::MOVED_TO)
+                    && let Err(e) = self.add_watches(&path)
+                {
+                    // it may be gone already, the build will tell
+                    warn!("{e}, its changes are not watched");
+                }
+                changes.insert(path);
+                last_event = Instant::now();
+            }
+        }
+        Ok(changes.into_iter().collect())
+    }
+}
+
+/// One line summary of a build, after some changes
+fn summary(changes: &[PathBuf], report: &BuildReport) -> String {
+    let mut summary = match changes.len() {
+        0 => "🔨 built".to_string(),
+        _ => {
+            let mut listed: Vec<_> = changes
+                .iter()
+                .take(LISTED_CHANGES)
+                .map(|path| path.display().to_string())
+                .collect();
+            if changes.len() > LISTED_CHANGES {
+                listed.push(format!("{} more", changes.len() - LISTED_CHANGES));
+            }
+            format!("🔁 {} changed", listed.join(", "))
+        }
+    };
+    summary.push_str(&format!(
+        " : {} converted, {} copied, {} error(s)",
+        report.converted,
+        report.copied,
+        report.errors.len()
+    ));
+    if !report.stale.is_empty() {
+        summary.push_str(&format!(
+            ", {} output(s) without source",
+            report.stale.len()
+        ));
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_tree::TestTree;
+
+    #[test]
+    fn test_summary() {
+        let report = BuildReport {
+            converted: 2,
+            unchanged: 10,
+            stale: vec![PathBuf::from("old.html")],
+            ..BuildReport::default()
+        };
+        assert_eq!(
+            summary(&[], &report),
+            "🔨 built : 2 converted, 0 copied, 0 error(s), 1 output(s) without source"
+        );
+        let changes: Vec<_> = ["a.gmi", "b.gmi", "c.gmi", "d.gmi", "e.gmi"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let report = BuildReport {
+            converted: 5,
+            ..BuildReport::default()
+        };
+        assert_eq!(
+            summary(&changes[..1], &report),
+            "🔁 a.gmi changed : 5 converted, 0 copied, 0 error(s)"
+        );
+        assert_eq!(
+            summary(&changes, &report),
+            "🔁 a.gmi, b.gmi, c.gmi, 2 more changed : 5 converted, 0 copied, 0 error(s)"
+        );
+    }
+    #[test]
+    fn test_wait_for_changes() {
+        let source_tree_directory = TestTree::new("watch_source");
+        let target_tree_directory = source_tree_directory.join("public");
+        fs::create_dir(&target_tree_directory).expect("unable to create test directory");
+        fs::write(source_tree_directory.join(".gemini2htmlignore"), "*.swp\n")
+            .expect("unable to write test file");
+        let mut watcher = Watcher::new(
+            &source_tree_directory,
+            &target_tree_directory,
+            &SiteOptions::default(),
+        )
+        .unwrap();
+        // a burst of events, some of them ignored, gives a single change
+        fs::write(source_tree_directory.join(".index.gmi.swp"), "swap")
+            .expect("unable to write test file");
+        fs::write(target_tree_directory.join("index.html"), "output")
+            .expect("unable to write test file");
+        for content in ["# first", "# second"] {
+            fs::write(source_tree_directory.join("index.gmi"), content)
+                .expect("unable to write test file");
+        }
+        assert_eq!(
+            watcher.wait_for_changes().unwrap(),
+            [PathBuf::from("index.gmi")]
+        );
+        // new directories are watched too
+        fs::create_dir(source_tree_directory.join("posts"))
+            .expect("unable to create test directory");
+        assert_eq!(
+            watcher.wait_for_changes().unwrap(),
+            [PathBuf::from("posts")]
+        );
+        fs::write(source_tree_directory.join("posts/first.gmi"), "# first")
+            .expect("unable to write test file");
+        assert_eq!(
+            watcher.wait_for_changes().unwrap(),
+            [PathBuf::from("posts/first.gmi")]
+        );
+        // a directory which can't be watched is not an error
+        fs::create_dir(source_tree_directory.join("gone"))
+            .expect("unable to create test directory");
+        fs::remove_dir(source_tree_directory.join("gone"))
+            .expect("unable to remove test directory");
+        assert_eq!(watcher.wait_for_changes().unwrap(), [PathBuf::from("gone")]);
+    }
+    #[test]
+    fn test_reload_filter() {
+        let source_tree_directory = TestTree::new("watch_reload_source");
+        let target_tree_directory = TestTree::new("watch_reload_target");
+        fs::create_dir(source_tree_directory.join("drafts"))
+            .expect("unable to create test directory");
+        fs::write(source_tree_directory.join(IGNORE_FILE_NAME), "drafts/\n")
+            .expect("unable to write test file");
+        let mut watcher = Watcher::new(
+            &source_tree_directory,
+            &target_tree_directory,
+            &SiteOptions::default(),
+        )
+        .unwrap();
+        // the ignore file changes, drafts are not ignored anymore
+        fs::write(source_tree_directory.join(IGNORE_FILE_NAME), "*.swp\n")
+            .expect("unable to write test file");
+        assert_eq!(
+            watcher.wait_for_changes().unwrap(),
+            [PathBuf::from(IGNORE_FILE_NAME)]
+        );
+        watcher.reload_filter(&SiteOptions::default()).unwrap();
+        fs::write(source_tree_directory.join("drafts/new.gmi"), "# new")
+            .expect("unable to write test file");
+        fs::write(source_tree_directory.join("new.swp"), "swap")
+            .expect("unable to write test file");
+        assert_eq!(
+            watcher.wait_for_changes().unwrap(),
+            [PathBuf::from("drafts/new.gmi")]
+        );
+    }
+}